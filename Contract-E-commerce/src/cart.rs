@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CartItem {
+  pub product_id: ProductId,
+  pub sku: Option<String>, // variant of the product
+  pub quantity: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+  // The cart is charged to the storage deposit of its account
+  pub fn add_to_cart(&mut self, product_id: ProductId, quantity: u64, sku: Option<String>) -> Vec<CartItem> {
+    self.assert_not_paused();
+    assert!(quantity > 0, "Quantity must be greater than 0");
+    assert!(product_id.len() <= MAX_PRODUCT_ID_LENGTH, "Product id is longer than {} bytes", MAX_PRODUCT_ID_LENGTH);
+    assert!(sku.iter().all(|sku| sku.len() <= MAX_SKU_LENGTH), "SKU is longer than {} bytes", MAX_SKU_LENGTH);
+    let product = self.internal_get_product(&product_id);
+    assert!(!product.delisted, "Product {} has been delisted", product_id);
+    // Panics if the variant does not exist
+    product.stock(sku.as_ref());
+    let account_id = env::predecessor_account_id();
+    let initial_storage_usage = env::storage_usage();
+
+    let mut cart = self.get_cart(account_id.clone());
+    match cart.iter_mut().find(|item| item.product_id == product_id && item.sku == sku) {
+      Some(item) => item.quantity += quantity,
+      None => cart.push(CartItem { product_id, sku, quantity }),
+    }
+    self.carts.insert(&account_id, &cart);
+    self.internal_update_storage(&account_id, initial_storage_usage);
+
+    cart
+  }
+
+  // Remove some items of a product, or the whole line if no quantity is given
+  pub fn remove_from_cart(
+    &mut self,
+    product_id: ProductId,
+    quantity: Option<u64>,
+    sku: Option<String>,
+  ) -> Vec<CartItem> {
+    self.assert_not_paused();
+    let account_id = env::predecessor_account_id();
+
+    let mut cart = self.get_cart(account_id.clone());
+    let index = cart
+      .iter()
+      .position(|item| item.product_id == product_id && item.sku == sku)
+      .expect("Product is not in your cart");
+    match quantity {
+      Some(quantity) if quantity < cart[index].quantity => cart[index].quantity -= quantity,
+      _ => {
+        cart.remove(index);
+      }
+    }
+    self.internal_save_cart(&account_id, &cart);
+
+    cart
+  }
+
+  pub fn clear_cart(&mut self) {
+    self.assert_not_paused();
+    self.internal_save_cart(&env::predecessor_account_id(), &Vec::new());
+  }
+
+  pub fn get_cart(&self, account_id: AccountId) -> Vec<CartItem> {
+    self.carts.get(&account_id).unwrap_or_default()
+  }
+
+  // Buy everything in the cart in one transaction, one order is created per line
+  // and each payment is released to the shop of the product.
+  // Every line is validated first so nothing is bought if one of them fails.
+  // A coupon is applied to every line it is valid for and counts as one use.
+  // The cart is the one of the predecessor, who attaches the deposit.
+  #[payable]
+  pub fn checkout(&mut self, coupon_code: Option<String>) -> Vec<Order> {
+    self.assert_not_paused();
+    let buyer = env::predecessor_account_id();
+    let cart = self.get_cart(buyer.clone());
+    assert!(!cart.is_empty(), "Your cart is empty");
+
+    // Lines of the same product share its stock and its sale, so they are checked one after another
+    let mut products: BTreeMap<ProductId, Product> = BTreeMap::new();
+    let mut lines: Vec<(Product, CartItem, Balance)> = Vec::new();
+    for item in cart.into_iter() {
+      let product =
+        products.entry(item.product_id.clone()).or_insert_with(|| self.internal_get_product(&item.product_id));
+      assert!(product.ft_token.is_none(), "Product {} is priced in a fungible token", product.product_id);
+      let price = self.internal_check_purchase(&buyer, product, item.sku.as_ref(), item.quantity);
+      let line_product = product.clone();
+      product.sell(item.sku.as_ref(), item.quantity);
+      lines.push((line_product, item, price));
+    }
+    if let Some(code) = coupon_code {
+      self.internal_apply_cart_coupon(&buyer, &code, &mut lines);
+    }
+    let total = lines
+      .iter()
+      .try_fold(0, |total: Balance, (_, _, price)| total.checked_add(*price))
+      .expect("Total price overflow");
+
+    let deposit = env::attached_deposit();
+    assert!(deposit >= total, "Not enough money");
+
+    let orders = lines
+      .into_iter()
+      .map(|(_, item, price)| self.internal_purchase(&buyer, &item.product_id, item.sku, item.quantity, price))
+      .collect();
+    self.internal_save_cart(&buyer, &Vec::new());
+
+    // Refund
+    if deposit > total {
+      Promise::new(buyer).transfer(deposit - total);
+    }
+
+    orders
+  }
+}
+
+impl Contract {
+  // Store the cart and give back the storage it no longer uses to its account
+  fn internal_save_cart(&mut self, account_id: &AccountId, cart: &Vec<CartItem>) {
+    let initial_storage_usage = env::storage_usage();
+    if cart.is_empty() {
+      self.carts.remove(account_id);
+    } else {
+      self.carts.insert(account_id, cart);
+    }
+    self.internal_update_storage(account_id, initial_storage_usage);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  // A second shop, `accounts(3)`, sells 5 "p2" at 50 yoctoNEAR
+  fn setup_two_shops() -> Contract {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(3));
+    set_context(accounts(3), 0);
+    contract.new_shop("Shop 2".to_string(), "desc".to_string());
+    contract.new_product("p2".to_string(), "Ao".to_string(), 5, 50, "desc".to_string(), None, None, None);
+    set_context(accounts(0), 0);
+    contract.set_platform_fee(1_000);
+
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p1".to_string(), 2, None);
+    contract.add_to_cart("p2".to_string(), 3, None);
+    contract
+  }
+
+  #[test]
+  fn checkout_pays_each_shop() {
+    let mut contract = setup_two_shops();
+
+    set_context(accounts(2), 400);
+    let orders = contract.checkout(None);
+    assert_eq!(orders.len(), 2);
+    assert_eq!((orders[0].seller.clone(), orders[0].amount), (accounts(1), 200));
+    assert_eq!((orders[1].seller.clone(), orders[1].amount), (accounts(3), 150));
+    assert_eq!(transfers(), vec![(accounts(2), 50)]);
+    assert_eq!(contract.get_total_escrow(), 350);
+    assert!(contract.get_cart(accounts(2)).is_empty());
+    assert_eq!(contract.storage_accounts.get(&accounts(2)).unwrap().used, 0);
+
+    // Each payment goes to the shop of its product, minus the 10% fee
+    for (order, payout) in orders.iter().zip([180, 135]) {
+      set_context(order.seller.clone(), 0);
+      contract.ship_order(order.order_id);
+      set_context(accounts(2), 0);
+      contract.confirm_delivery(order.order_id);
+      assert_eq!(transfers(), vec![(order.seller.clone(), payout)]);
+    }
+    assert_eq!(contract.get_platform_balance(), 35);
+    assert_eq!(contract.get_total_escrow(), 0);
+  }
+
+  #[test]
+  #[should_panic(expected = "Not enough money")]
+  fn checkout_needs_the_total() {
+    let mut contract = setup_two_shops();
+    set_context(accounts(2), 349);
+    contract.checkout(None);
+  }
+
+  #[test]
+  #[should_panic(expected = "Not enough p2 in stock")]
+  fn checkout_checks_every_line() {
+    let mut contract = setup_two_shops();
+    contract.add_to_cart("p2".to_string(), 3, None);
+    set_context(accounts(2), 1_000);
+    contract.checkout(None);
+  }
+
+  #[test]
+  fn checkout_uses_each_shop_coupon_once() {
+    let mut contract = setup_two_shops();
+    // Both shops have a "X" coupon, each usable once per buyer
+    set_context(accounts(1), 0);
+    contract.create_coupon("X".to_string(), Discount::Percentage(5_000), None, None, Some(1), None);
+    set_context(accounts(3), 0);
+    contract.create_coupon("X".to_string(), Discount::Fixed(30), None, None, Some(1), None);
+
+    set_context(accounts(2), 220);
+    let orders = contract.checkout(Some("X".to_string()));
+    assert_eq!(orders.iter().map(|order| order.amount).collect::<Vec<_>>(), vec![100, 120]);
+    assert_eq!(contract.get_coupon(accounts(1), "X".to_string()).uses, 1);
+    assert_eq!(contract.get_coupon(accounts(3), "X".to_string()).uses, 1);
+  }
+
+  #[test]
+  fn cart_is_charged_until_cleared() {
+    let mut contract = setup_two_shops();
+    let used = contract.storage_accounts.get(&accounts(2)).unwrap().used;
+    assert!(used > 0);
+    contract.remove_from_cart("p2".to_string(), None, None);
+    assert!(contract.storage_accounts.get(&accounts(2)).unwrap().used < used);
+    contract.clear_cart();
+    assert_eq!(contract.storage_accounts.get(&accounts(2)).unwrap().used, 0);
+  }
+
+  #[test]
+  #[should_panic(expected = "Register your account with storage_deposit first")]
+  fn cart_needs_a_storage_deposit() {
+    let mut contract = setup_contract();
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p1".to_string(), 1, None);
+  }
+
+  #[test]
+  #[should_panic(expected = "Product id is longer than 64 bytes")]
+  fn cart_product_id_is_bounded() {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p".repeat(MAX_PRODUCT_ID_LENGTH + 1), 1, None);
+  }
+}
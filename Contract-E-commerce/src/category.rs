@@ -0,0 +1,332 @@
+use crate::*;
+
+pub type CategoryId = String;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Category {
+  pub category_id: CategoryId,
+  pub name: String,
+  pub parent: Option<CategoryId>, // None for a root category
+}
+
+#[near_bindgen]
+impl Contract {
+  pub fn new_category(&mut self, category_id: CategoryId, name: String, parent: Option<CategoryId>) -> Category {
+    self.assert_role(Role::Admin);
+    assert!(self.categories.get(&category_id).is_none(), "Category already exists");
+    if let Some(parent) = parent.as_ref() {
+      assert!(self.categories.get(parent).is_some(), "Parent category not found");
+    }
+
+    let category = Category { category_id, name, parent };
+    self.categories.insert(&category.category_id, &category);
+    category
+  }
+
+  // Only a category without subcategories and products can be removed
+  pub fn remove_category(&mut self, category_id: CategoryId) {
+    self.assert_role(Role::Admin);
+    assert!(self.categories.get(&category_id).is_some(), "Category not found");
+    assert!(self.get_subcategories(Some(category_id.clone())).is_empty(), "Category has subcategories");
+    // Delisted products still belong to their category
+    assert!(self.products_per_category.get(&category_id).is_none(), "Category still has products");
+
+    self.categories.remove(&category_id);
+  }
+
+  pub fn get_category(&self, category_id: CategoryId) -> Category {
+    self.categories.get(&category_id).expect("Category not found")
+  }
+
+  pub fn get_categories(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Category> {
+    let (from_index, limit) = pagination(from_index, limit);
+    self.categories.values_as_vector().iter().skip(from_index).take(limit).collect()
+  }
+
+  // Children of a category, or the root categories if no parent is given
+  pub fn get_subcategories(&self, parent: Option<CategoryId>) -> Vec<Category> {
+    self.categories.values().filter(|category| category.parent == parent).collect()
+  }
+
+  pub fn get_products_by_category(
+    &self,
+    category_id: CategoryId,
+    from_index: Option<u64>,
+    limit: Option<u64>,
+  ) -> Vec<Product> {
+    let (from_index, limit) = pagination(from_index, limit);
+    match self.products_per_category.get(&category_id) {
+      Some(product_ids) => product_ids
+        .as_vector()
+        .iter()
+        .skip(from_index)
+        .take(limit)
+        .map(|product_id| self.internal_get_product(&product_id))
+        .filter(|product| !product.delisted)
+        .map(Product::with_current_price)
+        .collect(),
+      None => Vec::new(),
+    }
+  }
+
+  // Delisted products are not counted, as they are not listed
+  pub fn get_total_products_by_category(&self, category_id: CategoryId) -> u64 {
+    let total = self.products_per_category.get(&category_id).map(|product_ids| product_ids.len()).unwrap_or(0);
+    total - self.delisted_per_category.get(&category_id).unwrap_or(0)
+  }
+
+  pub fn get_products_by_tag(&self, tag: String, from_index: Option<u64>, limit: Option<u64>) -> Vec<Product> {
+    let (from_index, limit) = pagination(from_index, limit);
+    match self.products_per_tag.get(&normalize_tag(&tag)) {
+      Some(product_ids) => product_ids
+        .as_vector()
+        .iter()
+        .skip(from_index)
+        .take(limit)
+        .map(|product_id| self.internal_get_product(&product_id))
+        .filter(|product| !product.delisted)
+        .map(Product::with_current_price)
+        .collect(),
+      None => Vec::new(),
+    }
+  }
+
+  pub fn get_total_products_by_tag(&self, tag: String) -> u64 {
+    let tag = normalize_tag(&tag);
+    let total = self.products_per_tag.get(&tag).map(|product_ids| product_ids.len()).unwrap_or(0);
+    total - self.delisted_per_tag.get(&tag).unwrap_or(0)
+  }
+}
+
+impl Contract {
+  pub(crate) fn assert_category_exists(&self, category_id: &CategoryId) {
+    assert!(self.categories.get(category_id).is_some(), "Category not found");
+  }
+
+  pub(crate) fn internal_index_product_category(&mut self, product: &Product) {
+    if let Some(category_id) = product.category.as_ref() {
+      let mut product_ids = self.products_per_category.get(category_id).unwrap_or_else(|| {
+        UnorderedSet::new(
+          StorageKey::ProductsPerCategoryInnerKey { category_hash: env::sha256_array(category_id.as_bytes()) }
+            .try_to_vec()
+            .unwrap(),
+        )
+      });
+      product_ids.insert(&product.product_id);
+      self.products_per_category.insert(category_id, &product_ids);
+      if product.delisted {
+        count_delisted(&mut self.delisted_per_category, category_id, true);
+      }
+    }
+
+    for tag in product.tags.iter() {
+      let mut product_ids = self.products_per_tag.get(tag).unwrap_or_else(|| {
+        UnorderedSet::new(
+          StorageKey::ProductsPerTagInnerKey { tag_hash: env::sha256_array(tag.as_bytes()) }.try_to_vec().unwrap(),
+        )
+      });
+      product_ids.insert(&product.product_id);
+      self.products_per_tag.insert(tag, &product_ids);
+      if product.delisted {
+        count_delisted(&mut self.delisted_per_tag, tag, true);
+      }
+    }
+  }
+
+  pub(crate) fn internal_unindex_product_category(&mut self, product: &Product) {
+    if let Some(category_id) = product.category.as_ref() {
+      if let Some(mut product_ids) = self.products_per_category.get(category_id) {
+        product_ids.remove(&product.product_id);
+        if product_ids.is_empty() {
+          self.products_per_category.remove(category_id);
+        } else {
+          self.products_per_category.insert(category_id, &product_ids);
+        }
+      }
+      if product.delisted {
+        count_delisted(&mut self.delisted_per_category, category_id, false);
+      }
+    }
+
+    for tag in product.tags.iter() {
+      if let Some(mut product_ids) = self.products_per_tag.get(tag) {
+        product_ids.remove(&product.product_id);
+        if product_ids.is_empty() {
+          self.products_per_tag.remove(tag);
+        } else {
+          self.products_per_tag.insert(tag, &product_ids);
+        }
+      }
+      if product.delisted {
+        count_delisted(&mut self.delisted_per_tag, tag, false);
+      }
+    }
+  }
+}
+
+// Delisted products are counted per category and tag to leave them out of the product counts
+fn count_delisted(counts: &mut LookupMap<String, u64>, key: &String, delisted: bool) {
+  let count = counts.get(key).unwrap_or(0);
+  if delisted {
+    counts.insert(key, &(count + 1));
+  } else if count > 1 {
+    counts.insert(key, &(count - 1));
+  } else {
+    counts.remove(key);
+  }
+}
+
+// Tags are matched case-insensitively
+pub(crate) fn normalize_tag(tag: &str) -> String {
+  tag.trim().to_lowercase()
+}
+
+pub(crate) fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+  let mut tags: Vec<String> = tags.iter().map(|tag| normalize_tag(tag)).filter(|tag| !tag.is_empty()).collect();
+  tags.sort();
+  tags.dedup();
+  tags
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  // "clothes" with the subcategory "shirts", and "p2" and "p3" in "shirts"
+  fn setup_categories() -> Contract {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.new_category("clothes".to_string(), "Clothes".to_string(), None);
+    contract.new_category("shirts".to_string(), "Shirts".to_string(), Some("clothes".to_string()));
+    set_context(accounts(1), 0);
+    for (product_id, tags) in [("p2", vec![" Sale ", "cotton"]), ("p3", vec!["SALE", "sale", ""])] {
+      contract.new_product(
+        product_id.to_string(),
+        "Ao".to_string(),
+        5,
+        50,
+        "desc".to_string(),
+        None,
+        Some("shirts".to_string()),
+        Some(tags.into_iter().map(String::from).collect()),
+      );
+    }
+    contract
+  }
+
+  fn product_ids(products: Vec<Product>) -> Vec<ProductId> {
+    products.into_iter().map(|product| product.product_id).collect()
+  }
+
+  #[test]
+  fn categories_and_subcategories() {
+    let contract = setup_categories();
+    assert_eq!(contract.get_category("shirts".to_string()).parent, Some("clothes".to_string()));
+    assert_eq!(contract.get_categories(None, None).len(), 2);
+    let roots: Vec<CategoryId> =
+      contract.get_subcategories(None).into_iter().map(|category| category.category_id).collect();
+    assert_eq!(roots, vec!["clothes".to_string()]);
+    let children: Vec<CategoryId> = contract
+      .get_subcategories(Some("clothes".to_string()))
+      .into_iter()
+      .map(|category| category.category_id)
+      .collect();
+    assert_eq!(children, vec!["shirts".to_string()]);
+  }
+
+  #[test]
+  fn products_are_listed_by_category_and_tag() {
+    let contract = setup_categories();
+    assert_eq!(contract.get_product_by_id("p3".to_string()).tags, vec!["sale".to_string()]);
+    assert_eq!(product_ids(contract.get_products_by_category("shirts".to_string(), None, None)), vec!["p2", "p3"]);
+    assert_eq!(product_ids(contract.get_products_by_category("shirts".to_string(), Some(1), Some(1))), vec!["p3"]);
+    assert_eq!(product_ids(contract.get_products_by_tag("SALE".to_string(), None, None)), vec!["p2", "p3"]);
+    assert_eq!(product_ids(contract.get_products_by_tag("cotton".to_string(), None, None)), vec!["p2"]);
+    assert!(contract.get_products_by_category("clothes".to_string(), None, None).is_empty());
+
+    assert_eq!(contract.get_total_products_by_category("shirts".to_string()), 2);
+    assert_eq!(contract.get_total_products_by_category("clothes".to_string()), 0);
+    assert_eq!(contract.get_total_products_by_tag(" Sale".to_string()), 2);
+    assert_eq!(contract.get_total_products_by_tag("cotton".to_string()), 1);
+  }
+
+  #[test]
+  fn updating_a_product_moves_it() {
+    let mut contract = setup_categories();
+    set_context(accounts(1), 0);
+    contract.update_product(
+      "p2".to_string(),
+      None,
+      None,
+      None,
+      None,
+      Some("clothes".to_string()),
+      Some(vec!["linen".to_string()]),
+    );
+    assert_eq!(product_ids(contract.get_products_by_category("shirts".to_string(), None, None)), vec!["p3"]);
+    assert_eq!(product_ids(contract.get_products_by_category("clothes".to_string(), None, None)), vec!["p2"]);
+    assert_eq!(contract.get_total_products_by_tag("sale".to_string()), 1);
+    assert_eq!(contract.get_total_products_by_tag("cotton".to_string()), 0);
+    assert_eq!(contract.get_total_products_by_tag("linen".to_string()), 1);
+
+    contract.delete_product("p2".to_string());
+    assert_eq!(contract.get_total_products_by_category("clothes".to_string()), 0);
+    assert_eq!(contract.get_total_products_by_tag("linen".to_string()), 0);
+  }
+
+  #[test]
+  fn empty_category_can_be_removed() {
+    let mut contract = setup_categories();
+    set_context(accounts(1), 0);
+    contract.delete_product("p2".to_string());
+    contract.delete_product("p3".to_string());
+    set_context(accounts(0), 0);
+    contract.remove_category("shirts".to_string());
+    contract.remove_category("clothes".to_string());
+    assert!(contract.get_categories(None, None).is_empty());
+  }
+
+  #[test]
+  #[should_panic(expected = "Category still has products")]
+  fn category_with_products_can_not_be_removed() {
+    let mut contract = setup_categories();
+    set_context(accounts(0), 0);
+    contract.remove_category("shirts".to_string());
+  }
+
+  #[test]
+  #[should_panic(expected = "Category has subcategories")]
+  fn category_with_subcategories_can_not_be_removed() {
+    let mut contract = setup_categories();
+    set_context(accounts(0), 0);
+    contract.remove_category("clothes".to_string());
+  }
+
+  #[test]
+  #[should_panic(expected = "Parent category not found")]
+  fn parent_has_to_exist() {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.new_category("shirts".to_string(), "Shirts".to_string(), Some("clothes".to_string()));
+  }
+
+  #[test]
+  #[should_panic(expected = "Category not found")]
+  fn product_category_has_to_exist() {
+    let mut contract = setup_contract();
+    set_context(accounts(1), 0);
+    contract.update_product("p1".to_string(), None, None, None, None, Some("shirts".to_string()), None);
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires the Admin role")]
+  fn only_admins_create_categories() {
+    let mut contract = setup_contract();
+    set_context(accounts(1), 0);
+    contract.new_category("shirts".to_string(), "Shirts".to_string(), None);
+  }
+}
@@ -0,0 +1,204 @@
+use near_sdk::json_types::Base58CryptoHash;
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Discount {
+  Percentage(u32), // basis points of the price
+  Fixed(Balance),  // amount taken off the price, in the currency of the product
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Coupon {
+  pub code_hash: Base58CryptoHash, // sha256 of the owner and the code, the code itself is never stored
+  pub owner: AccountId,
+  pub discount: Discount,
+  pub product_id: Option<ProductId>, // None for every product of the shop
+  pub max_uses: Option<u32>,
+  pub per_account_limit: Option<u32>,
+  pub expires_at: Option<Timestamp>,
+  pub uses: u32,
+}
+
+impl Coupon {
+  pub fn applies_to(&self, product: &Product) -> bool {
+    product.owner == self.owner && (self.product_id.is_none() || self.product_id.as_ref() == Some(&product.product_id))
+  }
+
+  pub fn discount_for(&self, total_price: Balance) -> Balance {
+    let discount = match self.discount {
+      Discount::Percentage(percentage) => total_price * percentage as u128 / FEE_DIVISOR as u128,
+      Discount::Fixed(amount) => amount,
+    };
+    std::cmp::min(discount, total_price)
+  }
+
+  // Discount on each of `prices` bought together, a fixed amount is only taken off once
+  pub fn discounts_for(&self, prices: &[Balance]) -> Vec<Balance> {
+    let mut fixed_left = match self.discount {
+      Discount::Fixed(amount) => amount,
+      Discount::Percentage(_) => 0,
+    };
+    prices
+      .iter()
+      .map(|&price| match self.discount {
+        Discount::Percentage(_) => self.discount_for(price),
+        Discount::Fixed(_) => {
+          let discount = std::cmp::min(fixed_left, price);
+          fixed_left -= discount;
+          discount
+        }
+      })
+      .collect()
+  }
+}
+
+#[near_bindgen]
+impl Contract {
+  pub fn create_coupon(
+    &mut self,
+    code: String,
+    discount: Discount,
+    product_id: Option<ProductId>,
+    max_uses: Option<u32>,
+    per_account_limit: Option<u32>,
+    expires_at: Option<Timestamp>,
+  ) -> Coupon {
+    self.assert_not_paused();
+    let owner = env::signer_account_id();
+    self.internal_get_shop(&owner);
+    let code_hash = coupon_hash(&owner, &code);
+    assert!(self.internal_coupon(&owner, &code).is_none(), "Coupon code already exists");
+    if let Discount::Percentage(percentage) = discount {
+      assert!(percentage <= FEE_DIVISOR, "Percentage can not be greater than {}", FEE_DIVISOR);
+    }
+    if let Some(product_id) = product_id.as_ref() {
+      assert_eq!(self.internal_get_product(product_id).owner, owner, "Unauthorized");
+    }
+    let initial_storage_usage = env::storage_usage();
+
+    let coupon = Coupon { code_hash, owner, discount, product_id, max_uses, per_account_limit, expires_at, uses: 0 };
+    self.coupons.insert(&code_hash, &coupon);
+    let mut coupon_hashes = self.coupons_per_shop.get(&coupon.owner).unwrap_or_default();
+    coupon_hashes.push(code_hash);
+    self.coupons_per_shop.insert(&coupon.owner, &coupon_hashes);
+    self.internal_update_storage(&coupon.owner, initial_storage_usage);
+
+    coupon
+  }
+
+  pub fn remove_coupon(&mut self, code_hash: Base58CryptoHash) {
+    self.assert_not_paused();
+    let owner = env::signer_account_id();
+    let coupon = self.coupons.get(&code_hash).expect("Coupon not found");
+    assert_eq!(coupon.owner, owner, "Unauthorized");
+    let initial_storage_usage = env::storage_usage();
+
+    self.coupons.remove(&code_hash);
+    let mut coupon_hashes = self.coupons_per_shop.get(&owner).unwrap_or_default();
+    coupon_hashes.retain(|hash| hash != &code_hash);
+    self.coupons_per_shop.insert(&owner, &coupon_hashes);
+    self.internal_refund_storage(&owner, initial_storage_usage);
+  }
+
+  pub fn get_coupon(&self, owner: AccountId, code: String) -> Coupon {
+    self.internal_coupon(&owner, &code).expect("Coupon not found")
+  }
+
+  pub fn get_coupons_by_shop(&self, owner: AccountId) -> Vec<Coupon> {
+    let coupon_hashes = self.coupons_per_shop.get(&owner).unwrap_or_default();
+    coupon_hashes.iter().filter_map(|code_hash| self.coupons.get(code_hash)).collect()
+  }
+}
+
+impl Contract {
+  // Coupons created before codes were per shop (state version 2) are stored under the hash of the code alone
+  fn internal_coupon(&self, owner: &AccountId, code: &str) -> Option<Coupon> {
+    self.coupons.get(&coupon_hash(owner, code)).or_else(|| {
+      let legacy_hash: Base58CryptoHash = env::sha256_array(code.as_bytes()).into();
+      self.coupons.get(&legacy_hash).filter(|coupon| &coupon.owner == owner)
+    })
+  }
+
+  // The coupon of the shop of `owner` with this code, if it has one
+  pub(crate) fn internal_find_coupon(&self, owner: &AccountId, code: &str) -> Option<Coupon> {
+    let coupon = self.internal_coupon(owner, code)?;
+    if let Some(expires_at) = coupon.expires_at {
+      assert!(env::block_timestamp() < expires_at, "Coupon has expired");
+    }
+    Some(coupon)
+  }
+
+  // Discount of a coupon on the purchase of one product
+  pub(crate) fn internal_apply_coupon(
+    &mut self,
+    buyer: &AccountId,
+    product: &Product,
+    code: &str,
+    total_price: Balance,
+  ) -> Balance {
+    let mut coupon = self.internal_find_coupon(&product.owner, code).expect("Coupon not found");
+    assert!(coupon.applies_to(product), "Coupon does not apply to {}", product.product_id);
+    self.internal_redeem_coupon(&mut coupon, buyer, total_price)
+  }
+
+  // Take the coupon of each shop of the cart off the lines it applies to, one use per shop.
+  // `lines` are the products with their price before the discount.
+  pub(crate) fn internal_apply_cart_coupon(
+    &mut self,
+    buyer: &AccountId,
+    code: &str,
+    lines: &mut [(Product, CartItem, Balance)],
+  ) {
+    let mut owners: Vec<AccountId> = lines.iter().map(|(product, _, _)| product.owner.clone()).collect();
+    owners.sort();
+    owners.dedup();
+
+    let mut applied = false;
+    for owner in owners {
+      let Some(mut coupon) = self.internal_find_coupon(&owner, code) else { continue };
+      let eligible: Vec<usize> = (0..lines.len()).filter(|&index| coupon.applies_to(&lines[index].0)).collect();
+      if eligible.is_empty() {
+        continue;
+      }
+      let prices: Vec<Balance> = eligible.iter().map(|&index| lines[index].2).collect();
+      self.internal_redeem_coupon(&mut coupon, buyer, prices.iter().sum());
+      for (index, discount) in eligible.into_iter().zip(coupon.discounts_for(&prices)) {
+        lines[index].2 -= discount;
+      }
+      applied = true;
+    }
+    assert!(applied, "Coupon does not apply to your cart");
+  }
+
+  // Count one use of the coupon by the buyer and return the discount on `total_price`
+  pub(crate) fn internal_redeem_coupon(
+    &mut self,
+    coupon: &mut Coupon,
+    buyer: &AccountId,
+    total_price: Balance,
+  ) -> Balance {
+    if let Some(max_uses) = coupon.max_uses {
+      assert!(coupon.uses < max_uses, "Coupon has been used up");
+    }
+    let use_key = (coupon.code_hash, buyer.clone());
+    let account_uses = self.coupon_uses.get(&use_key).unwrap_or(0);
+    if let Some(per_account_limit) = coupon.per_account_limit {
+      assert!(account_uses < per_account_limit, "You have already used this coupon");
+    }
+
+    coupon.uses += 1;
+    self.coupons.insert(&coupon.code_hash, coupon);
+    self.coupon_uses.insert(&use_key, &(account_uses + 1));
+
+    coupon.discount_for(total_price)
+  }
+}
+
+// Codes are per shop, two shops can both have a "SALE" coupon.
+// Account ids can not contain ':' so it separates the owner from the code.
+pub(crate) fn coupon_hash(owner: &AccountId, code: &str) -> Base58CryptoHash {
+  env::sha256_array(format!("{}:{}", owner, code).as_bytes()).into()
+}
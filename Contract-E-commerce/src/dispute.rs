@@ -0,0 +1,295 @@
+use crate::*;
+
+pub const MAX_EVIDENCE_LENGTH: usize = 1000;
+pub const MAX_EVIDENCE_PER_DISPUTE: usize = 20;
+pub const MAX_CONTENT_HASHES: usize = 10; // per piece of evidence
+pub const MAX_CONTENT_HASH_LENGTH: usize = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Evidence {
+  pub author: AccountId,
+  pub text: String,
+  pub content_hashes: Vec<String>, // hashes or IPFS CIDs of photos and documents kept off-chain
+  pub created_at: Timestamp,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DisputeStatus {
+  Open,
+  Resolved,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Dispute {
+  pub order_id: OrderId,
+  pub opened_by: AccountId,
+  pub reason: String,
+  pub evidence: Vec<Evidence>,
+  pub status: DisputeStatus,
+  pub arbitrator: Option<AccountId>,
+  pub buyer_amount: Balance, // paid back to the buyer, the rest goes to the seller
+  pub created_at: Timestamp,
+  pub resolved_at: Option<Timestamp>,
+}
+
+#[near_bindgen]
+impl Contract {
+  // Buyer or seller: freeze the payment until an arbitrator settles the order, one dispute per order.
+  // The dispute is charged to the storage deposit of who opens it and each piece of evidence to its author,
+  // until the dispute is resolved.
+  pub fn open_dispute(&mut self, order_id: OrderId, reason: String) -> Dispute {
+    self.assert_not_paused();
+    let mut order = self.get_order(order_id);
+    let caller = env::signer_account_id();
+    assert!(caller == order.buyer || caller == order.seller, "Unauthorized");
+    assert!(matches!(order.escrow_status, EscrowStatus::Held | EscrowStatus::Frozen), "Payment is not held in escrow");
+    assert!(self.disputes.get(&order_id).is_none(), "Order already has a dispute");
+    assert!(reason.len() <= MAX_EVIDENCE_LENGTH, "Reason is longer than {} bytes", MAX_EVIDENCE_LENGTH);
+
+    order.escrow_status = EscrowStatus::Frozen;
+    order.updated_at = env::block_timestamp();
+    self.orders.insert(&order_id, &order);
+
+    let initial_storage_usage = env::storage_usage();
+    let dispute = Dispute {
+      order_id,
+      opened_by: caller,
+      reason,
+      evidence: Vec::new(),
+      status: DisputeStatus::Open,
+      arbitrator: None,
+      buyer_amount: 0,
+      created_at: env::block_timestamp(),
+      resolved_at: None,
+    };
+    self.disputes.insert(&order_id, &dispute);
+    self.open_disputes.insert(&order_id);
+    self.internal_update_storage(&dispute.opened_by, initial_storage_usage);
+    Event::DisputeOpen(vec![DisputeData { order_id: U128(order_id), opened_by: dispute.opened_by.clone() }]).emit();
+    dispute
+  }
+
+  // Buyer or seller: add a statement and the hashes of files backing it
+  pub fn add_evidence(&mut self, order_id: OrderId, text: String, content_hashes: Vec<String>) -> Dispute {
+    self.assert_not_paused();
+    let order = self.get_order(order_id);
+    let author = env::signer_account_id();
+    assert!(author == order.buyer || author == order.seller, "Unauthorized");
+    let mut dispute = self.get_dispute(order_id);
+    assert_eq!(dispute.status, DisputeStatus::Open, "Dispute is already resolved");
+    assert!(
+      dispute.evidence.len() < MAX_EVIDENCE_PER_DISPUTE,
+      "Too much evidence, at most {}",
+      MAX_EVIDENCE_PER_DISPUTE
+    );
+    assert!(text.len() <= MAX_EVIDENCE_LENGTH, "Evidence is longer than {} bytes", MAX_EVIDENCE_LENGTH);
+    assert!(content_hashes.len() <= MAX_CONTENT_HASHES, "Too many content hashes, at most {}", MAX_CONTENT_HASHES);
+    assert!(
+      content_hashes.iter().all(|content_hash| content_hash.len() <= MAX_CONTENT_HASH_LENGTH),
+      "Content hash is longer than {} bytes",
+      MAX_CONTENT_HASH_LENGTH
+    );
+
+    let initial_storage_usage = env::storage_usage();
+    dispute.evidence.push(Evidence {
+      author: author.clone(),
+      text,
+      content_hashes,
+      created_at: env::block_timestamp(),
+    });
+    self.disputes.insert(&order_id, &dispute);
+    self.internal_update_storage(&author, initial_storage_usage);
+    dispute
+  }
+
+  // Arbitrator: pay `buyer_amount` back to the buyer and the rest of the order amount to the seller,
+  // an arbitrator can not settle their own orders
+  pub fn resolve_dispute(&mut self, order_id: OrderId, buyer_amount: Balance) -> Dispute {
+    self.assert_not_paused();
+    self.assert_role(Role::Arbitrator);
+    let mut dispute = self.get_dispute(order_id);
+    assert_eq!(dispute.status, DisputeStatus::Open, "Dispute is already resolved");
+    let mut order = self.get_order(order_id);
+    let arbitrator = env::predecessor_account_id();
+    assert!(arbitrator != order.buyer && arbitrator != order.seller, "You can not settle your own order");
+
+    self.internal_split_escrow(&mut order, buyer_amount);
+    self.internal_set_order_status(&mut order, OrderStatus::Resolved);
+
+    self.internal_free_dispute(&dispute);
+    dispute.status = DisputeStatus::Resolved;
+    dispute.arbitrator = Some(arbitrator.clone());
+    dispute.buyer_amount = buyer_amount;
+    dispute.resolved_at = Some(env::block_timestamp());
+    self.disputes.insert(&order_id, &dispute);
+    Event::DisputeResolve(vec![DisputeResolveData {
+      order_id: U128(order_id),
+      arbitrator,
+      buyer_amount: U128(buyer_amount),
+      seller_amount: U128(order.amount - buyer_amount),
+    }])
+    .emit();
+    dispute
+  }
+
+  pub fn get_dispute(&self, order_id: OrderId) -> Dispute {
+    self.disputes.get(&order_id).expect("Dispute not found")
+  }
+
+  // Arbitrator: the disputes waiting for a decision
+  pub fn get_open_disputes(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Dispute> {
+    let (from_index, limit) = pagination(from_index, limit);
+    self.open_disputes.iter().skip(from_index).take(limit).filter_map(|order_id| self.disputes.get(&order_id)).collect()
+  }
+}
+
+impl Contract {
+  // Give back the storage of the open dispute to who paid for it: each author the size of their evidence
+  // and who opened it the rest. The resolved dispute is kept at the expense of the contract.
+  fn internal_free_dispute(&mut self, dispute: &Dispute) {
+    let initial_storage_usage = env::storage_usage();
+    self.open_disputes.remove(&dispute.order_id);
+    self.disputes.remove(&dispute.order_id);
+    let mut freed = initial_storage_usage - env::storage_usage();
+
+    for evidence in dispute.evidence.iter() {
+      let size = evidence.try_to_vec().unwrap().len() as StorageUsage;
+      self.internal_free_storage(&evidence.author, size);
+      freed -= size;
+    }
+    self.internal_free_storage(&dispute.opened_by, freed);
+  }
+
+  // The payment of a disputed order can only be moved by the arbitrator
+  pub(crate) fn assert_not_disputed(&self, order_id: OrderId) {
+    assert!(!self.open_disputes.contains(&order_id), "Order is under dispute");
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::{accounts, VMContextBuilder};
+  use near_sdk::testing_env;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  // `accounts(3)` is an arbitrator and order 1 of `accounts(2)` is disputed
+  fn setup_dispute(quantity: u64) -> (Contract, Order) {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.set_platform_fee(1_000);
+    contract.grant_role(accounts(3), Role::Arbitrator);
+    register(&mut contract, accounts(2));
+    let order = buy(&mut contract, accounts(2), quantity);
+    set_context(accounts(2), 0);
+    contract.open_dispute(order.order_id, "broken".to_string());
+    (contract, order)
+  }
+
+  #[test]
+  fn split_pays_both_sides() {
+    let (mut contract, order) = setup_dispute(3);
+
+    set_context(accounts(3), 0);
+    contract.resolve_dispute(order.order_id, 100);
+    let order = contract.get_order(order.order_id);
+    assert_eq!((order.escrow_status, order.refunded, order.fee), (EscrowStatus::Split, 100, 20));
+    assert_eq!(transfers(), vec![(accounts(2), 100), (accounts(1), 180)]);
+    assert_eq!(contract.get_platform_balance(), 20);
+    assert_eq!(contract.get_total_escrow(), 0);
+  }
+
+  #[test]
+  fn split_of_a_large_order() {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.set_platform_fee(1_000);
+    contract.grant_role(accounts(3), Role::Arbitrator);
+    set_context(accounts(1), 0);
+    contract.update_product("p1".to_string(), None, None, Some(1_000 * ONE_NEAR), None, None, None);
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 1_000 * ONE_NEAR);
+    let order = contract.buy_product("p1".to_string(), 1, None, None);
+    contract.open_dispute(order.order_id, "broken".to_string());
+
+    // The contract holds the payment
+    let mut builder = VMContextBuilder::new();
+    builder
+      .current_account_id(accounts(0))
+      .signer_account_id(accounts(3))
+      .predecessor_account_id(accounts(3))
+      .account_balance(2_000 * ONE_NEAR);
+    testing_env!(builder.build());
+    contract.resolve_dispute(order.order_id, 400 * ONE_NEAR);
+    assert_eq!(transfers(), vec![(accounts(2), 400 * ONE_NEAR), (accounts(1), 540 * ONE_NEAR)]);
+    assert_eq!(contract.get_platform_balance(), 60 * ONE_NEAR);
+  }
+
+  #[test]
+  #[should_panic(expected = "Order is under dispute")]
+  fn disputed_order_can_not_be_confirmed() {
+    let (mut contract, order) = setup_dispute(1);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+    set_context(accounts(2), 0);
+    contract.confirm_delivery(order.order_id);
+  }
+
+  #[test]
+  #[should_panic(expected = "You can not settle your own order")]
+  fn arbitrator_can_not_settle_own_order() {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.grant_role(accounts(2), Role::Arbitrator);
+    register(&mut contract, accounts(2));
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context(accounts(2), 0);
+    contract.open_dispute(order.order_id, "broken".to_string());
+    contract.resolve_dispute(order.order_id, 100);
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires the Arbitrator role")]
+  fn admin_can_not_settle() {
+    let (mut contract, order) = setup_dispute(1);
+    set_context(accounts(0), 0);
+    contract.resolve_dispute(order.order_id, 100);
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires the Moderator role")]
+  fn arbitrator_can_not_moderate() {
+    let (mut contract, _) = setup_dispute(1);
+    set_context(accounts(3), 0);
+    contract.delist_product("p1".to_string());
+  }
+
+  #[test]
+  #[should_panic(expected = "Too many content hashes, at most 10")]
+  fn content_hashes_are_capped() {
+    let (mut contract, order) = setup_dispute(1);
+    set_context(accounts(2), 0);
+    contract.add_evidence(order.order_id, "photos".to_string(), vec!["Qm".to_string(); MAX_CONTENT_HASHES + 1]);
+  }
+
+  #[test]
+  fn dispute_is_charged_until_resolved() {
+    let (mut contract, order) = setup_dispute(1);
+    let used = |contract: &Contract, account_id: AccountId| contract.storage_accounts.get(&account_id).unwrap().used;
+    let opener_used = used(&contract, accounts(2));
+    assert!(opener_used > 0);
+    set_context(accounts(1), 0);
+    let seller_used = used(&contract, accounts(1));
+    contract.add_evidence(order.order_id, "shipped".to_string(), vec!["Qm".to_string()]);
+    assert!(used(&contract, accounts(1)) > seller_used);
+
+    set_context(accounts(3), 0);
+    contract.resolve_dispute(order.order_id, 0);
+    assert_eq!((used(&contract, accounts(2)), used(&contract, accounts(1))), (0, seller_used));
+    assert_eq!(contract.get_dispute(order.order_id).evidence.len(), 1);
+  }
+}
@@ -0,0 +1,252 @@
+use self::u256::U256;
+use crate::*;
+
+// 7 days
+pub const DEFAULT_ESCROW_TIMEOUT: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+// For the fee share of a split, `fee * seller_amount` does not fit in a u128
+#[allow(clippy::assign_op_pattern, clippy::manual_div_ceil)]
+mod u256 {
+  uint::construct_uint! {
+    pub struct U256(4);
+  }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum EscrowStatus {
+  Unpaid,
+  Held,     // waiting for the buyer to confirm receipt or for the timeout
+  Frozen,   // the buyer opened a claim
+  Released, // paid out to the seller
+  Refunded, // paid back to the buyer
+  Split,    // shared between the buyer and the seller by an arbitrator
+}
+
+#[near_bindgen]
+impl Contract {
+  // Buyer: freeze the payment before it is released to the seller
+  pub fn open_claim(&mut self, order_id: OrderId, reason: String) -> Order {
+    self.assert_not_paused();
+    let mut order = self.get_order(order_id);
+    assert_eq!(order.buyer, env::signer_account_id(), "Only the buyer can open a claim");
+    assert_eq!(order.escrow_status, EscrowStatus::Held, "Payment is not held in escrow");
+    assert!(order.status == OrderStatus::Paid || env::block_timestamp() < order.release_at, "Claim period is over");
+
+    order.escrow_status = EscrowStatus::Frozen;
+    order.claim = Some(reason);
+    order.updated_at = env::block_timestamp();
+    self.orders.insert(&order_id, &order);
+    order
+  }
+
+  // Buyer: withdraw the claim, the payment goes back to the normal release flow
+  pub fn close_claim(&mut self, order_id: OrderId) -> Order {
+    self.assert_not_paused();
+    let mut order = self.get_order(order_id);
+    assert_eq!(order.buyer, env::signer_account_id(), "Only the buyer can close a claim");
+    assert_eq!(order.escrow_status, EscrowStatus::Frozen, "There is no open claim");
+    self.assert_not_disputed(order_id);
+
+    order.escrow_status = EscrowStatus::Held;
+    order.claim = None;
+    order.updated_at = env::block_timestamp();
+    self.orders.insert(&order_id, &order);
+    order
+  }
+
+  // Anyone: pay the seller once the order has been shipped for the timeout and no claim was opened
+  pub fn release_escrow(&mut self, order_id: OrderId) -> Order {
+    self.assert_not_paused();
+    let mut order = self.get_order(order_id);
+    assert_eq!(order.escrow_status, EscrowStatus::Held, "Payment is not held in escrow");
+    assert_eq!(order.status, OrderStatus::Shipped, "Only a shipped order can be released");
+    self.assert_not_disputed(order_id);
+    assert!(env::block_timestamp() >= order.release_at, "Payment is still locked");
+
+    self.internal_set_order_status(&mut order, OrderStatus::Delivered);
+    self.internal_release_escrow(&mut order);
+    order
+  }
+
+  pub fn set_escrow_timeout(&mut self, timeout: u64) {
+    self.assert_role(Role::Admin);
+    self.escrow_timeout = timeout;
+  }
+
+  pub fn get_escrow_timeout(&self) -> u64 {
+    self.escrow_timeout
+  }
+
+  // NEAR held in escrow
+  pub fn get_total_escrow(&self) -> Balance {
+    self.total_escrow
+  }
+}
+
+impl Contract {
+  pub(crate) fn internal_hold_escrow(&mut self, order: &mut Order) {
+    order.escrow_status = EscrowStatus::Held;
+    if order.ft_token.is_none() {
+      self.total_escrow += order.amount;
+    }
+    self.orders.insert(&order.order_id, order);
+  }
+
+  pub(crate) fn internal_release_escrow(&mut self, order: &mut Order) {
+    self.internal_split_escrow(order, 0);
+  }
+
+  pub(crate) fn internal_refund_escrow(&mut self, order: &mut Order) {
+    self.internal_split_escrow(order, order.amount);
+  }
+
+  // Pay `buyer_amount` back to the buyer and the rest to the seller,
+  // the platform fee is only taken from the part paid to the seller
+  pub(crate) fn internal_split_escrow(&mut self, order: &mut Order, buyer_amount: Balance) {
+    assert!(matches!(order.escrow_status, EscrowStatus::Held | EscrowStatus::Frozen), "Payment is not held in escrow");
+    assert!(buyer_amount <= order.amount, "Can not refund more than the order amount");
+    let seller_amount = order.amount - buyer_amount;
+    let fee = match order.amount {
+      0 => 0,
+      amount => (U256::from(order.fee) * U256::from(seller_amount) / U256::from(amount)).as_u128(),
+    };
+
+    order.escrow_status = match seller_amount {
+      0 => EscrowStatus::Refunded,
+      _ if buyer_amount == 0 => EscrowStatus::Released,
+      _ => EscrowStatus::Split,
+    };
+    order.fee = fee;
+    order.refunded = buyer_amount;
+    if order.ft_token.is_none() {
+      self.total_escrow -= order.amount;
+    }
+    self.orders.insert(&order.order_id, order);
+
+    if buyer_amount > 0 {
+      self.internal_transfer(order.buyer.clone(), order.ft_token.clone(), buyer_amount);
+      Event::Refund(vec![PaymentData {
+        order_id: U128(order.order_id),
+        receiver_id: order.buyer.clone(),
+        amount: U128(buyer_amount),
+        fee: U128(0),
+        ft_token: order.ft_token.clone(),
+      }])
+      .emit();
+    }
+    if seller_amount > 0 {
+      self.internal_collect_fee(order.ft_token.as_ref(), fee);
+      if seller_amount > fee {
+        self.internal_transfer(order.seller.clone(), order.ft_token.clone(), seller_amount - fee);
+      }
+      Event::Payout(vec![PaymentData {
+        order_id: U128(order.order_id),
+        receiver_id: order.seller.clone(),
+        amount: U128(seller_amount - fee),
+        fee: U128(fee),
+        ft_token: order.ft_token.clone(),
+      }])
+      .emit();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  #[test]
+  fn purchase_holds_payment() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 3);
+
+    assert_eq!(order.escrow_status, EscrowStatus::Held);
+    assert_eq!(order.release_at, 0);
+    assert_eq!(contract.get_total_escrow(), 300);
+    assert!(transfers().is_empty());
+  }
+
+  #[test]
+  fn confirm_delivery_pays_seller() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 3);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+
+    set_context(accounts(2), 0);
+    let order = contract.confirm_delivery(order.order_id);
+    assert_eq!(order.escrow_status, EscrowStatus::Released);
+    assert_eq!(transfers(), vec![(accounts(1), 300)]);
+    assert_eq!(contract.get_total_escrow(), 0);
+  }
+
+  #[test]
+  fn cancel_paid_order_refunds_buyer() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 3);
+
+    set_context(accounts(2), 0);
+    let order = contract.cancel_order(order.order_id);
+    assert_eq!(order.escrow_status, EscrowStatus::Refunded);
+    assert_eq!(order.refunded, 300);
+    assert_eq!(transfers(), vec![(accounts(2), 300)]);
+    assert_eq!(contract.get_total_escrow(), 0);
+    assert_eq!(contract.get_product_by_id("p1".to_string()).total_supply, 10);
+  }
+
+  #[test]
+  fn release_after_timeout_since_shipping() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context_at(accounts(1), 0, 1_000);
+    let order = contract.ship_order(order.order_id);
+    assert_eq!(order.release_at, 1_000 + DEFAULT_ESCROW_TIMEOUT);
+
+    set_context_at(accounts(3), 0, order.release_at);
+    let order = contract.release_escrow(order.order_id);
+    assert_eq!(order.status, OrderStatus::Delivered);
+    assert_eq!(order.escrow_status, EscrowStatus::Released);
+    assert_eq!(transfers(), vec![(accounts(1), 100)]);
+  }
+
+  #[test]
+  #[should_panic(expected = "Only a shipped order can be released")]
+  fn unshipped_order_is_not_released() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+
+    set_context_at(accounts(3), 0, DEFAULT_ESCROW_TIMEOUT + 1);
+    contract.release_escrow(order.order_id);
+  }
+
+  #[test]
+  #[should_panic(expected = "Payment is still locked")]
+  fn shipped_order_is_locked_until_timeout() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context_at(accounts(1), 0, 1_000);
+    let order = contract.ship_order(order.order_id);
+
+    set_context_at(accounts(3), 0, order.release_at - 1);
+    contract.release_escrow(order.order_id);
+  }
+
+  #[test]
+  fn claim_refunded_by_seller() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 2);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+    set_context(accounts(2), 0);
+    assert_eq!(contract.open_claim(order.order_id, "broken".to_string()).escrow_status, EscrowStatus::Frozen);
+
+    set_context(accounts(1), 0);
+    let order = contract.cancel_order(order.order_id);
+    assert_eq!(order.status, OrderStatus::Refunded);
+    assert_eq!(transfers(), vec![(accounts(2), 200)]);
+  }
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+
+use near_sdk::json_types::U128;
+use near_sdk::serde_json;
+
+use crate::*;
+
+pub const EVENT_STANDARD: &str = "ecommerce";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+// NEP-297 events, logged as `EVENT_JSON:{"standard":"ecommerce","version":"1.0.0","event":...,"data":[...]}`
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+  ShopCreate(Vec<ShopEventData>),
+  ShopUpdate(Vec<ShopEventData>),
+  ShopClose(Vec<ShopEventData>),
+  ShopTransfer(Vec<ShopTransferData>),
+  ProductList(Vec<ProductEventData>),
+  ProductUpdate(Vec<ProductEventData>),
+  ProductDelist(Vec<ProductEventData>),
+  LowStock(Vec<LowStockData>),
+  Purchase(Vec<PurchaseData>),
+  OrderStatus(Vec<OrderStatusData>),
+  Payout(Vec<PaymentData>),
+  Refund(Vec<PaymentData>),
+  RoleGrant(Vec<RoleData>),
+  RoleRevoke(Vec<RoleData>),
+  Pause(Vec<PauseData>),
+  Unpause(Vec<PauseData>),
+  ReportCreate(Vec<ReportData>),
+  ReportClose(Vec<ReportStatusData>),
+  AccountBan(Vec<AccountData>),
+  AccountUnban(Vec<AccountData>),
+  DisputeOpen(Vec<DisputeData>),
+  DisputeResolve(Vec<DisputeResolveData>),
+  ReturnRequest(Vec<ReturnData>),
+  ReturnApprove(Vec<ReturnData>),
+  ReturnReject(Vec<ReturnData>),
+  PriceDrop(Vec<PriceDropData>),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ShopEventData {
+  pub owner: AccountId,
+  pub name: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ShopTransferData {
+  pub old_owner: AccountId,
+  pub new_owner: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProductEventData {
+  pub product_id: ProductId,
+  pub owner: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LowStockData {
+  pub product_id: ProductId,
+  pub sku: Option<String>,
+  pub total_supply: u64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PurchaseData {
+  pub order_id: U128,
+  pub buyer: AccountId,
+  pub seller: AccountId,
+  pub product_id: ProductId,
+  pub sku: Option<String>,
+  pub quantity: u64,
+  pub amount: U128,
+  pub ft_token: Option<AccountId>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderStatusData {
+  pub order_id: U128,
+  pub status: OrderStatus,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PaymentData {
+  pub order_id: U128,
+  pub receiver_id: AccountId,
+  pub amount: U128,
+  pub fee: U128, // taken by the platform, only for payouts
+  pub ft_token: Option<AccountId>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoleData {
+  pub account_id: AccountId,
+  pub role: Role,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PauseData {
+  pub account_id: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReportData {
+  pub report_id: ReportId,
+  pub reporter: AccountId,
+  pub target: ReportTarget,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReportStatusData {
+  pub report_id: ReportId,
+  pub status: ReportStatus,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountData {
+  pub account_id: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DisputeData {
+  pub order_id: U128,
+  pub opened_by: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DisputeResolveData {
+  pub order_id: U128,
+  pub arbitrator: AccountId,
+  pub buyer_amount: U128,
+  pub seller_amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReturnData {
+  pub order_id: U128,
+  pub quantity: u64,
+  pub status: ReturnStatus,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceDropData {
+  pub product_id: ProductId,
+  pub old_price: U128,
+  pub new_price: U128,
+  pub wishlisters: u64, // listed by `get_wishlisters`
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+  standard: &'static str,
+  version: &'static str,
+  #[serde(flatten)]
+  event: &'a Event,
+}
+
+impl fmt::Display for Event {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let log = EventLog { standard: EVENT_STANDARD, version: EVENT_VERSION, event: self };
+    write!(f, "EVENT_JSON:{}", serde_json::to_string(&log).map_err(|_| fmt::Error)?)
+  }
+}
+
+impl Event {
+  pub fn emit(&self) {
+    env::log_str(&self.to_string());
+  }
+}
+
+impl From<&Shop> for ShopEventData {
+  fn from(shop: &Shop) -> Self {
+    Self { owner: shop.owner.clone(), name: shop.name.clone() }
+  }
+}
+
+impl From<&Product> for ProductEventData {
+  fn from(product: &Product) -> Self {
+    Self { product_id: product.product_id.clone(), owner: product.owner.clone() }
+  }
+}
+
+impl From<&Order> for PurchaseData {
+  fn from(order: &Order) -> Self {
+    Self {
+      order_id: U128(order.order_id),
+      buyer: order.buyer.clone(),
+      seller: order.seller.clone(),
+      product_id: order.product_id.clone(),
+      sku: order.sku.clone(),
+      quantity: order.quantity,
+      amount: U128(order.amount),
+      ft_token: order.ft_token.clone(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::{accounts, get_logs};
+
+  use super::*;
+  use crate::test_utils::*;
+
+  // The JSON logged after the NEP-297 prefix
+  fn parse(log: &str) -> serde_json::Value {
+    serde_json::from_str(log.strip_prefix("EVENT_JSON:").expect("Not a NEP-297 event")).unwrap()
+  }
+
+  #[test]
+  fn event_is_logged_in_the_nep297_format() {
+    let event = Event::Payout(vec![PaymentData {
+      order_id: U128(1),
+      receiver_id: accounts(1),
+      amount: U128(98),
+      fee: U128(2),
+      ft_token: None,
+    }]);
+    assert_eq!(
+      event.to_string(),
+      r#"EVENT_JSON:{"standard":"ecommerce","version":"1.0.0","event":"payout","data":[{"order_id":"1","receiver_id":"bob","amount":"98","fee":"2","ft_token":null}]}"#
+    );
+  }
+
+  #[test]
+  fn emitted_events_are_logged() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 2);
+    let logs: Vec<serde_json::Value> = get_logs().iter().map(|log| parse(log)).collect();
+    let events: Vec<&str> = logs.iter().map(|log| log["event"].as_str().unwrap()).collect();
+    assert_eq!(events, vec!["product_update", "purchase", "order_status"]);
+
+    assert_eq!(
+      (logs[1]["standard"].as_str(), logs[1]["version"].as_str()),
+      (Some(EVENT_STANDARD), Some(EVENT_VERSION))
+    );
+    let data = &logs[1]["data"][0];
+    assert_eq!(data["order_id"], order.order_id.to_string());
+    assert_eq!(data["buyer"], accounts(2).to_string());
+    assert_eq!((data["quantity"].as_u64(), data["amount"].as_str()), (Some(2), Some("200")));
+    assert_eq!(logs[2]["data"][0]["status"], "Paid");
+  }
+
+  #[test]
+  fn role_and_pause_events() {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.grant_role(accounts(2), Role::Admin);
+    contract.pause();
+    let logs: Vec<serde_json::Value> = get_logs().iter().map(|log| parse(log)).collect();
+    assert_eq!((logs[0]["event"].as_str(), logs[1]["event"].as_str()), (Some("role_grant"), Some("pause")));
+    assert_eq!(logs[0]["data"][0]["role"], "Admin");
+    assert_eq!(logs[1]["data"][0]["account_id"], accounts(0).to_string());
+  }
+}
@@ -0,0 +1,257 @@
+use near_contract_standards::fungible_token::core::ext_ft_core;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::U128;
+use near_sdk::{is_promise_success, serde_json, Gas, PromiseOrValue};
+
+use crate::*;
+
+pub const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+pub const GAS_FOR_RESOLVE_FT_TRANSFER: Gas = Gas(5_000_000_000_000);
+
+// `msg` of `ft_transfer_call` when paying for a product
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtPurchaseMsg {
+  pub product_id: ProductId,
+  pub quantity: u64,
+  pub coupon_code: Option<String>,
+  pub sku: Option<String>,
+}
+
+// `msg` of `ft_transfer_call` when the seller approves the return of an order,
+// the tokens sent are the refund to the buyer
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtReturnMsg {
+  pub order_id: OrderId,
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+  // Called by the token contract on `ft_transfer_call`, returns the unused tokens to the sender
+  fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+    self.assert_not_paused();
+    let token_id = env::predecessor_account_id();
+    assert!(self.accepted_tokens.contains(&token_id), "Token {} is not accepted", token_id);
+
+    if let Ok(purchase) = serde_json::from_str::<FtPurchaseMsg>(&msg) {
+      return self.internal_ft_purchase(sender_id, token_id, amount, purchase);
+    }
+    let FtReturnMsg { order_id } = serde_json::from_str(&msg).expect("Invalid transfer message");
+    let order = self.get_order(order_id);
+    assert_eq!(order.ft_token.as_ref(), Some(&token_id), "Order was not paid in {}", token_id);
+    assert_eq!(order.seller, sender_id, "Only the seller can approve a return");
+    self.internal_approve_return(order, amount.0);
+    PromiseOrValue::Value(U128(0))
+  }
+}
+
+impl Contract {
+  fn internal_ft_purchase(
+    &mut self,
+    sender_id: AccountId,
+    token_id: AccountId,
+    amount: U128,
+    purchase: FtPurchaseMsg,
+  ) -> PromiseOrValue<U128> {
+    let FtPurchaseMsg { product_id, quantity, coupon_code, sku } = purchase;
+    let product = self.internal_get_product(&product_id);
+    assert_eq!(product.ft_token.as_ref(), Some(&token_id), "Product is not priced in {}", token_id);
+    let mut total_price = self.internal_check_purchase(&sender_id, &product, sku.as_ref(), quantity);
+    if let Some(code) = coupon_code {
+      total_price -= self.internal_apply_coupon(&sender_id, &product, &code, total_price);
+    }
+    assert!(amount.0 >= total_price, "Not enough tokens");
+
+    self.internal_purchase(&sender_id, &product_id, sku, quantity, total_price);
+
+    PromiseOrValue::Value(U128(amount.0 - total_price))
+  }
+}
+
+#[near_bindgen]
+impl Contract {
+  pub fn add_accepted_token(&mut self, token_id: AccountId) {
+    self.assert_role(Role::Admin);
+    self.accepted_tokens.insert(&token_id);
+  }
+
+  pub fn remove_accepted_token(&mut self, token_id: AccountId) {
+    self.assert_role(Role::Admin);
+    self.accepted_tokens.remove(&token_id);
+  }
+
+  pub fn get_accepted_tokens(&self) -> Vec<AccountId> {
+    self.accepted_tokens.to_vec()
+  }
+
+  // Send again the tokens of transfers to the caller that failed, e.g. because it was not registered on the token
+  pub fn claim_ft(&mut self, token_id: AccountId) {
+    let account_id = env::predecessor_account_id();
+    let amount = self.ft_claims.remove(&(account_id.clone(), token_id.clone())).expect("Nothing to claim");
+    self.internal_transfer(account_id, Some(token_id), amount);
+  }
+
+  pub fn get_ft_claim(&self, account_id: AccountId, token_id: AccountId) -> Balance {
+    self.ft_claims.get(&(account_id, token_id)).unwrap_or(0)
+  }
+
+  // Callback of every `ft_transfer`, keeps the tokens of a failed transfer for the receiver to claim.
+  // Failed withdrawals of the platform go back to its balance.
+  #[private]
+  pub fn on_ft_transfer(&mut self, token_id: AccountId, receiver_id: AccountId, amount: U128) {
+    if is_promise_success() {
+      return;
+    }
+    if receiver_id == self.platform_name {
+      let balance = self.get_platform_ft_balance(token_id.clone());
+      self.platform_ft_balances.insert(&token_id, &(balance + amount.0));
+    } else {
+      let key = (receiver_id, token_id);
+      let claim = self.ft_claims.get(&key).unwrap_or(0);
+      self.ft_claims.insert(&key, &(claim + amount.0));
+    }
+  }
+}
+
+impl Contract {
+  // Send NEAR, or fungible tokens if a token is given
+  pub(crate) fn internal_transfer(&self, receiver_id: AccountId, ft_token: Option<AccountId>, amount: Balance) {
+    if amount == 0 {
+      return;
+    }
+
+    match ft_token {
+      Some(token_id) => {
+        ext_ft_core::ext(token_id.clone())
+          .with_attached_deposit(1)
+          .with_static_gas(GAS_FOR_FT_TRANSFER)
+          .ft_transfer(receiver_id.clone(), U128(amount), None)
+          .then(Self::ext(env::current_account_id()).with_static_gas(GAS_FOR_RESOLVE_FT_TRANSFER).on_ft_transfer(
+            token_id,
+            receiver_id,
+            U128(amount),
+          ));
+      }
+      None => {
+        Promise::new(receiver_id).transfer(amount);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::{accounts, VMContextBuilder};
+  use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+  use super::*;
+  use crate::test_utils::*;
+
+  fn mock_ft() -> AccountId {
+    "mock-ft.testnet".parse().unwrap()
+  }
+
+  // `accounts(1)` also sells 10 "pf" at 100 tokens of the mock FT contract
+  fn setup_ft() -> Contract {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.add_accepted_token(mock_ft());
+    set_context(accounts(1), 0);
+    contract.new_product("pf".to_string(), "Ao".to_string(), 10, 100, "desc".to_string(), Some(mock_ft()), None, None);
+    contract
+  }
+
+  // The call the token contract makes on `ft_transfer_call` from `sender_id`
+  fn ft_transfer_call(
+    contract: &mut Contract,
+    token_id: AccountId,
+    sender_id: AccountId,
+    amount: Balance,
+    msg: &str,
+  ) -> Balance {
+    let mut builder = VMContextBuilder::new();
+    builder.current_account_id(accounts(0)).signer_account_id(sender_id.clone()).predecessor_account_id(token_id);
+    testing_env!(builder.build());
+    match contract.ft_on_transfer(sender_id, U128(amount), msg.to_string()) {
+      PromiseOrValue::Value(unused) => unused.0,
+      PromiseOrValue::Promise(_) => panic!("Expected a value"),
+    }
+  }
+
+  fn set_promise_result(result: PromiseResult) {
+    let mut builder = VMContextBuilder::new();
+    builder.current_account_id(accounts(0)).predecessor_account_id(accounts(0));
+    testing_env!(builder.build(), VMConfig::test(), RuntimeFeesConfig::test(), Default::default(), vec![result]);
+  }
+
+  #[test]
+  fn ft_purchase_returns_change() {
+    let mut contract = setup_ft();
+    let unused = ft_transfer_call(&mut contract, mock_ft(), accounts(2), 350, r#"{"product_id": "pf", "quantity": 3}"#);
+    assert_eq!(unused, 50);
+
+    let order = contract.get_orders_by_buyer(accounts(2)).pop().unwrap();
+    assert_eq!((order.amount, order.ft_token.clone()), (300, Some(mock_ft())));
+    assert_eq!(order.escrow_status, EscrowStatus::Held);
+    assert_eq!(contract.get_total_escrow(), 0);
+
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+    set_context(accounts(2), 0);
+    contract.confirm_delivery(order.order_id);
+    assert_eq!(ft_transfers(), vec![(mock_ft(), accounts(1), 300)]);
+  }
+
+  #[test]
+  #[should_panic(expected = "Invalid transfer message")]
+  fn ft_transfer_with_bad_msg() {
+    let mut contract = setup_ft();
+    ft_transfer_call(&mut contract, mock_ft(), accounts(2), 100, "buy pf");
+  }
+
+  #[test]
+  #[should_panic(expected = "Not enough tokens")]
+  fn ft_purchase_underpaid() {
+    let mut contract = setup_ft();
+    ft_transfer_call(&mut contract, mock_ft(), accounts(2), 99, r#"{"product_id": "pf", "quantity": 1}"#);
+  }
+
+  #[test]
+  #[should_panic(expected = "is not accepted")]
+  fn ft_transfer_from_unknown_token() {
+    let mut contract = setup_ft();
+    ft_transfer_call(&mut contract, accounts(4), accounts(2), 100, r#"{"product_id": "pf", "quantity": 1}"#);
+  }
+
+  #[test]
+  fn failed_payout_can_be_claimed() {
+    let mut contract = setup_ft();
+    set_promise_result(PromiseResult::Failed);
+    contract.on_ft_transfer(mock_ft(), accounts(1), U128(300));
+    assert_eq!(contract.get_ft_claim(accounts(1), mock_ft()), 300);
+
+    set_promise_result(PromiseResult::Successful(Vec::new()));
+    contract.on_ft_transfer(mock_ft(), accounts(1), U128(300));
+    assert_eq!(contract.get_ft_claim(accounts(1), mock_ft()), 300);
+
+    set_context(accounts(1), 0);
+    contract.claim_ft(mock_ft());
+    assert_eq!(ft_transfers(), vec![(mock_ft(), accounts(1), 300)]);
+    assert_eq!(contract.get_ft_claim(accounts(1), mock_ft()), 0);
+  }
+
+  #[test]
+  fn failed_platform_withdrawal_is_restored() {
+    let mut contract = setup_ft();
+    contract.internal_collect_fee(Some(&mock_ft()), 25);
+    set_context(accounts(0), 0);
+    contract.withdraw_platform_ft_balance(mock_ft(), None);
+    assert_eq!(ft_transfers(), vec![(mock_ft(), accounts(0), 25)]);
+    assert_eq!(contract.get_platform_ft_balance(mock_ft()), 0);
+
+    set_promise_result(PromiseResult::Failed);
+    contract.on_ft_transfer(mock_ft(), accounts(0), U128(25));
+    assert_eq!(contract.get_platform_ft_balance(mock_ft()), 25);
+  }
+}
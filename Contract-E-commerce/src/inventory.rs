@@ -0,0 +1,321 @@
+use crate::*;
+
+// 15 minutes
+pub const RESERVATION_TIMEOUT: u64 = 15 * 60 * 1_000_000_000;
+// Lines of a cart that can be reserved at once
+pub const MAX_RESERVED_LINES: usize = 10;
+// Buyers holding stock of the same product at once
+pub const MAX_RESERVATIONS_PER_PRODUCT: usize = 20;
+// Quantity of a product, or of one of its variants, an account can hold at once
+pub const MAX_RESERVED_QUANTITY: u64 = 10;
+
+// Stock held for a buyer between `reserve_cart` and `checkout`, other buyers can not take it until it expires
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StockReservation {
+  pub account_id: AccountId,
+  pub sku: Option<String>,
+  pub quantity: u64,
+  pub expires_at: Timestamp,
+}
+
+impl StockReservation {
+  pub fn is_active(&self) -> bool {
+    env::block_timestamp() < self.expires_at
+  }
+}
+
+#[near_bindgen]
+impl Contract {
+  pub fn restock(&mut self, product_id: ProductId, quantity: u64, sku: Option<String>) -> Product {
+    self.assert_not_paused();
+    let mut product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+    assert!(quantity > 0, "Quantity must be greater than 0");
+    let stock = product.stock(sku.as_ref());
+
+    product.set_stock(sku.as_ref(), stock.checked_add(quantity).expect("Stock overflow"));
+    self.internal_update_product(&product);
+    product
+  }
+
+  // Set the stock to the counted quantity, e.g. after an inventory check
+  pub fn adjust_stock(&mut self, product_id: ProductId, total_supply: u64, sku: Option<String>) -> Product {
+    self.assert_not_paused();
+    let mut product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+
+    product.set_stock(sku.as_ref(), total_supply);
+    self.internal_update_product(&product);
+    product
+  }
+
+  // A `low_stock` event is emitted when the stock of the product or of one of its variants
+  // goes down to `threshold`
+  pub fn set_low_stock_threshold(&mut self, product_id: ProductId, threshold: Option<u64>) -> Product {
+    self.assert_not_paused();
+    let mut product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+    let initial_storage_usage = env::storage_usage();
+
+    product.low_stock_threshold = threshold;
+    self.internal_update_product(&product);
+    self.internal_update_storage(&product.owner, initial_storage_usage);
+    product
+  }
+
+  // Hold the stock of the lines of the cart for `RESERVATION_TIMEOUT`, returns the lines held.
+  // Calling it again updates the quantities but a live hold keeps its expiry, so it can not be renewed forever.
+  // A line is left unreserved when its product already has `MAX_RESERVATIONS_PER_PRODUCT` holds, it can
+  // still be bought from the stock nobody holds.
+  // Each reservation is charged to the storage deposit of the buyer until it is released or replaced.
+  pub fn reserve_cart(&mut self) -> Vec<(ProductId, StockReservation)> {
+    self.assert_not_paused();
+    let account_id = env::predecessor_account_id();
+    let cart = self.get_cart(account_id.clone());
+    assert!(!cart.is_empty(), "Your cart is empty");
+    assert!(cart.len() <= MAX_RESERVED_LINES, "Can not reserve more than {} lines", MAX_RESERVED_LINES);
+
+    let mut held = Vec::new();
+    for item in cart {
+      assert!(
+        item.quantity <= MAX_RESERVED_QUANTITY,
+        "Can not reserve more than {} of {}",
+        MAX_RESERVED_QUANTITY,
+        item.product_id
+      );
+      let product = self.internal_get_product(&item.product_id);
+      let available = self.internal_available_stock(&product, item.sku.as_ref(), Some(&account_id));
+      assert!(available >= item.quantity, "Not enough {} in stock", product.product_id);
+
+      let mut reservations = self.internal_active_reservations(&item.product_id);
+      let is_own = |reservation: &StockReservation| reservation.account_id == account_id && reservation.sku == item.sku;
+      let expires_at = match reservations.iter().find(|reservation| is_own(reservation)) {
+        Some(reservation) => reservation.expires_at,
+        None if reservations.len() < MAX_RESERVATIONS_PER_PRODUCT => env::block_timestamp() + RESERVATION_TIMEOUT,
+        None => continue,
+      };
+      reservations.retain(|reservation| !is_own(reservation));
+      let reservation =
+        StockReservation { account_id: account_id.clone(), sku: item.sku, quantity: item.quantity, expires_at };
+      reservations.push(reservation.clone());
+      self.internal_save_reservations(&item.product_id, reservations);
+      held.push((item.product_id, reservation));
+    }
+
+    held
+  }
+
+  pub fn release_reservations(&mut self) {
+    self.assert_not_paused();
+    let account_id = env::predecessor_account_id();
+    for item in self.get_cart(account_id.clone()) {
+      self.internal_release_reservation(&item.product_id, item.sku.as_ref(), &account_id);
+    }
+  }
+
+  pub fn get_reservations(&self, product_id: ProductId) -> Vec<StockReservation> {
+    self.internal_active_reservations(&product_id)
+  }
+
+  // Stock that is not reserved by anyone
+  pub fn get_available_stock(&self, product_id: ProductId, sku: Option<String>) -> u64 {
+    let product = self.internal_get_product(&product_id);
+    self.internal_available_stock(&product, sku.as_ref(), None)
+  }
+}
+
+impl Contract {
+  fn internal_active_reservations(&self, product_id: &ProductId) -> Vec<StockReservation> {
+    let mut reservations = self.reservations.get(product_id).unwrap_or_default();
+    reservations.retain(StockReservation::is_active);
+    reservations
+  }
+
+  // Stock left once the reservations of other accounts are taken out
+  pub(crate) fn internal_available_stock(
+    &self,
+    product: &Product,
+    sku: Option<&String>,
+    account_id: Option<&AccountId>,
+  ) -> u64 {
+    let reserved: u64 = self
+      .internal_active_reservations(&product.product_id)
+      .iter()
+      .filter(|reservation| reservation.sku.as_ref() == sku && Some(&reservation.account_id) != account_id)
+      .map(|reservation| reservation.quantity)
+      .sum();
+    product.stock(sku).saturating_sub(reserved)
+  }
+
+  // Drop the reservation of the account, expired reservations of the product are dropped at the same time
+  pub(crate) fn internal_release_reservation(
+    &mut self,
+    product_id: &ProductId,
+    sku: Option<&String>,
+    account_id: &AccountId,
+  ) {
+    let mut reservations = self.internal_active_reservations(product_id);
+    reservations.retain(|reservation| &reservation.account_id != account_id || reservation.sku.as_ref() != sku);
+    self.internal_save_reservations(product_id, reservations);
+  }
+
+  // Store the reservations of the product. A reservation takes its size in the storage of its account
+  // from when it is added until it is dropped, expired ones included.
+  pub(crate) fn internal_save_reservations(&mut self, product_id: &ProductId, reservations: Vec<StockReservation>) {
+    let stored = self.reservations.get(product_id).unwrap_or_default();
+    let size = |reservation: &StockReservation| reservation.try_to_vec().unwrap().len() as StorageUsage;
+    for reservation in stored.iter().filter(|reservation| !reservations.contains(reservation)) {
+      self.internal_free_storage(&reservation.account_id, size(reservation));
+    }
+    for reservation in reservations.iter().filter(|reservation| !stored.contains(reservation)) {
+      self.internal_charge_storage(&reservation.account_id, size(reservation));
+    }
+
+    if reservations.is_empty() {
+      self.reservations.remove(product_id);
+    } else {
+      self.reservations.insert(product_id, &reservations);
+    }
+  }
+
+  pub(crate) fn internal_check_low_stock(&self, old_product: &Product, product: &Product) {
+    let Some(threshold) = product.low_stock_threshold else {
+      return;
+    };
+    let crossed = |before: u64, after: u64| before > threshold && after <= threshold;
+
+    let mut low_stock = Vec::new();
+    if crossed(old_product.total_supply, product.total_supply) {
+      low_stock.push(LowStockData {
+        product_id: product.product_id.clone(),
+        sku: None,
+        total_supply: product.total_supply,
+      });
+    }
+    for variant in product.variants.iter() {
+      if let Some(old_variant) = old_product.variants.iter().find(|old_variant| old_variant.sku == variant.sku) {
+        if crossed(old_variant.total_supply, variant.total_supply) {
+          low_stock.push(LowStockData {
+            product_id: product.product_id.clone(),
+            sku: Some(variant.sku.clone()),
+            total_supply: variant.total_supply,
+          });
+        }
+      }
+    }
+    if !low_stock.is_empty() {
+      Event::LowStock(low_stock).emit();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  fn used(contract: &Contract, account_id: AccountId) -> StorageUsage {
+    contract.storage_accounts.get(&account_id).unwrap().used
+  }
+
+  #[test]
+  fn reservation_is_charged_until_released() {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p1".to_string(), 8, None);
+    let cart_used = used(&contract, accounts(2));
+    contract.reserve_cart();
+    assert!(used(&contract, accounts(2)) > cart_used);
+    assert_eq!(contract.get_available_stock("p1".to_string(), None), 2);
+
+    contract.release_reservations();
+    assert_eq!(used(&contract, accounts(2)), cart_used);
+    assert_eq!(contract.get_available_stock("p1".to_string(), None), 10);
+  }
+
+  #[test]
+  fn expired_reservation_is_given_back() {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p1".to_string(), 8, None);
+    let cart_used = used(&contract, accounts(2));
+    contract.reserve_cart();
+
+    // Dropped when someone else buys the product
+    set_context_at(accounts(3), 100, RESERVATION_TIMEOUT);
+    contract.buy_product("p1".to_string(), 1, None, None);
+    assert_eq!(used(&contract, accounts(2)), cart_used);
+  }
+
+  #[test]
+  fn full_reservations_leave_the_line_unreserved() {
+    let mut contract = setup_contract();
+    contract.restock("p1".to_string(), 100, None);
+    for index in 0..MAX_RESERVATIONS_PER_PRODUCT {
+      let account_id: AccountId = format!("buyer{}.near", index).parse().unwrap();
+      register(&mut contract, account_id.clone());
+      set_context(account_id, 0);
+      contract.add_to_cart("p1".to_string(), 1, None);
+      assert_eq!(contract.reserve_cart().len(), 1);
+    }
+
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p1".to_string(), 1, None);
+    let cart_used = used(&contract, accounts(2));
+    assert!(contract.reserve_cart().is_empty());
+    assert_eq!(used(&contract, accounts(2)), cart_used);
+    set_context(accounts(2), 100);
+    assert_eq!(contract.checkout(None).len(), 1);
+    assert_eq!(used(&contract, accounts(2)), 0);
+  }
+
+  #[test]
+  #[should_panic(expected = "Can not reserve more than 10 of p1")]
+  fn reserved_quantity_is_capped() {
+    let mut contract = setup_contract();
+    contract.restock("p1".to_string(), 100, None);
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p1".to_string(), MAX_RESERVED_QUANTITY + 1, None);
+    contract.reserve_cart();
+  }
+
+  #[test]
+  fn renewal_keeps_the_expiry() {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p1".to_string(), 2, None);
+    let expires_at = contract.reserve_cart()[0].1.expires_at;
+
+    set_context_at(accounts(2), 0, RESERVATION_TIMEOUT - 1);
+    contract.add_to_cart("p1".to_string(), 1, None);
+    let held = contract.reserve_cart();
+    assert_eq!(held[0].1.quantity, 3);
+    assert_eq!(held[0].1.expires_at, expires_at);
+
+    set_context_at(accounts(3), 0, RESERVATION_TIMEOUT);
+    assert_eq!(contract.get_available_stock("p1".to_string(), None), 10);
+  }
+
+  #[test]
+  fn deleted_product_gives_back_the_reservations() {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p1".to_string(), 2, None);
+    let cart_used = used(&contract, accounts(2));
+    contract.reserve_cart();
+    assert!(used(&contract, accounts(2)) > cart_used);
+
+    set_context(accounts(1), 0);
+    contract.delete_product("p1".to_string());
+    assert_eq!(used(&contract, accounts(2)), cart_used);
+  }
+}
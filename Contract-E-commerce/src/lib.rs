@@ -1,32 +1,109 @@
+// Contract methods take their JSON arguments one by one
+#![allow(clippy::too_many_arguments)]
+
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::json_types::{Base58CryptoHash, U128};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{env, near_bindgen, AccountId, Balance, CryptoHash, PanicOnDefault};
-
-pub type ProductId = String;
-
-#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
-#[serde(crate = "near_sdk::serde")]
-pub struct Product {
-  pub product_id: ProductId,
-  pub name: String,
-  pub total_supply: u64,
-  pub price: Balance,
-  pub desc: String, // description
-  pub owner: AccountId,
-}
+use near_sdk::{env, near_bindgen, AccountId, Balance, CryptoHash, PanicOnDefault, Promise, StorageUsage, Timestamp};
+
+pub use crate::cart::*;
+pub use crate::category::*;
+pub use crate::coupon::*;
+pub use crate::dispute::*;
+pub use crate::escrow::*;
+pub use crate::events::*;
+pub use crate::ft::*;
+pub use crate::inventory::*;
+pub use crate::migrate::*;
+pub use crate::moderation::*;
+pub use crate::order::*;
+pub use crate::platform::*;
+pub use crate::product::*;
+pub use crate::reputation::*;
+pub use crate::returns::*;
+pub use crate::review::*;
+pub use crate::roles::*;
+pub use crate::sale::*;
+pub use crate::shop::*;
+pub use crate::storage::*;
+pub use crate::variant::*;
+pub use crate::wishlist::*;
+
+mod cart;
+mod category;
+mod coupon;
+mod dispute;
+mod escrow;
+mod events;
+mod ft;
+mod inventory;
+mod migrate;
+mod moderation;
+mod order;
+mod platform;
+mod product;
+mod reputation;
+mod returns;
+mod review;
+mod roles;
+mod sale;
+mod search;
+mod shop;
+mod storage;
+#[cfg(test)]
+mod test_utils;
+mod variant;
+mod wishlist;
+
+// Items returned by a listing view when no limit is given
+pub const DEFAULT_PAGE_LIMIT: u64 = 50;
 
-#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize)]
-#[serde(crate = "near_sdk::serde")]
-pub struct Shop {
-  pub owner: AccountId,
-  pub name: String,
-  pub desc: String,
-  pub total_product: u64,
-}
 #[derive(BorshDeserialize, BorshSerialize)]
 pub enum StorageKey {
   ProductPerOwnerKey,
+  OrdersKey,
+  OrdersPerBuyerKey,
+  OrdersPerShopKey,
+  CartsKey,
+  AcceptedTokensKey,
+  PlatformFtBalancesKey,
+  StorageAccountsKey,
+  ProductIndexKey,
+  ShopIndexKey,
+  PendingShopTransfersKey,
+  CategoriesKey,
+  ProductsPerCategoryKey,
+  ProductsPerCategoryInnerKey { category_hash: CryptoHash },
+  ProductsPerTagKey,
+  ProductsPerTagInnerKey { tag_hash: CryptoHash },
+  ProductNameIndexKey,
+  ProductNameIndexInnerKey { token_hash: CryptoHash },
+  ShopNameIndexKey,
+  ShopNameIndexInnerKey { token_hash: CryptoHash },
+  ReviewsPerProductKey,
+  ReviewsPerProductInnerKey { product_hash: CryptoHash },
+  CouponsKey,
+  CouponsPerShopKey,
+  CouponUsesKey,
+  ReservationsKey,
+  RolesKey,
+  ReportsKey,
+  OpenReportsKey,
+  BannedAccountsKey,
+  DisputesKey,
+  OpenDisputesKey,
+  ReturnsKey,
+  WishlistsKey,
+  WishlistedByKey,
+  WishlistedByInnerKey { product_hash: CryptoHash },
+  FtClaimsKey,
+  ProductsPerShopInnerKey { account_hash: CryptoHash },
+  OpenReportsByIdKey,
+  DelistedPerShopKey,
+  DelistedPerCategoryKey,
+  DelistedPerTagKey,
+  ChargedCartsKey,
 }
 
 // Define the contract structure
@@ -34,58 +111,105 @@ pub enum StorageKey {
 #[derive(PanicOnDefault, BorshDeserialize, BorshSerialize)]
 pub struct Contract {
   pub platform_name: AccountId,
-  pub products_per_shop: UnorderedMap<AccountId, Vec<Product>>,
+  pub products_per_shop: LookupMap<AccountId, UnorderedSet<ProductId>>,
   pub product_by_id: LookupMap<ProductId, Product>,
   pub products: UnorderedMap<u128, Product>,
   pub shops: LookupMap<AccountId, Shop>,
   pub all_shops: UnorderedMap<u128, Shop>,
   pub total_shops: u128,
   pub total_products: u128,
+  pub orders: LookupMap<OrderId, Order>,
+  pub orders_per_buyer: LookupMap<AccountId, Vec<OrderId>>,
+  pub orders_per_shop: LookupMap<AccountId, Vec<OrderId>>,
+  pub total_orders: u128,
+  pub escrow_timeout: u64,
+  pub total_escrow: Balance,
+  pub platform_fee: u32,
+  pub platform_balance: Balance,
+  pub total_platform_fees: Balance,
+  pub carts: LookupMap<AccountId, Vec<CartItem>>, // charged to their account
+  pub accepted_tokens: UnorderedSet<AccountId>,
+  pub platform_ft_balances: LookupMap<AccountId, Balance>,
+  pub storage_accounts: LookupMap<AccountId, StorageAccount>,
+  pub account_storage_usage: StorageUsage,
+  pub product_index: LookupMap<ProductId, u128>, // key of the product in `products`
+  pub shop_index: LookupMap<AccountId, u128>,    // key of the shop in `all_shops`
+  pub pending_shop_transfers: LookupMap<AccountId, AccountId>,
+  pub categories: UnorderedMap<CategoryId, Category>,
+  pub products_per_category: LookupMap<CategoryId, UnorderedSet<ProductId>>,
+  pub products_per_tag: LookupMap<String, UnorderedSet<ProductId>>,
+  pub product_name_index: TreeMap<String, UnorderedSet<ProductId>>, // lowercased name word -> products
+  pub shop_name_index: TreeMap<String, UnorderedSet<AccountId>>,
+  pub reviews_per_product: LookupMap<ProductId, Vector<Review>>,
+  pub coupons: LookupMap<Base58CryptoHash, Coupon>,
+  pub coupons_per_shop: LookupMap<AccountId, Vec<Base58CryptoHash>>,
+  pub coupon_uses: LookupMap<(Base58CryptoHash, AccountId), u32>,
+  pub reservations: LookupMap<ProductId, Vec<StockReservation>>,
+  pub roles: UnorderedMap<AccountId, Role>, // admins and moderators, the owner is `platform_name`
+  pub paused: bool,
+  pub reports: LookupMap<ReportId, Report>,
+  pub open_reports: TreeMap<ReportId, ()>, // ordered by id, so oldest first
+  pub total_reports: u64,
+  pub banned_accounts: UnorderedSet<AccountId>,
+  pub disputes: LookupMap<OrderId, Dispute>,
+  pub open_disputes: UnorderedSet<OrderId>,
+  pub returns: LookupMap<OrderId, ReturnRequest>,
+  pub ft_claims: LookupMap<(AccountId, AccountId), Balance>, // (receiver, token) -> tokens of failed transfers
+  pub total_delisted: u64,
+  pub delisted_per_shop: LookupMap<AccountId, u64>,
+  pub wishlists: LookupMap<AccountId, Vec<Wishlist>>,
+  pub wishlisted_by: LookupMap<ProductId, UnorderedSet<AccountId>>,
+  pub delisted_per_category: LookupMap<CategoryId, u64>,
+  pub delisted_per_tag: LookupMap<String, u64>,
 }
 
 #[near_bindgen]
 impl Contract {
   #[init]
   pub fn init() -> Self {
-    Self {
-      platform_name: env::signer_account_id(),
-      products_per_shop: UnorderedMap::new(StorageKey::ProductPerOwnerKey.try_to_vec().unwrap()),
-      product_by_id: LookupMap::new(b"product by id".try_to_vec().unwrap()),
-      products: UnorderedMap::new(b"products".try_to_vec().unwrap()),
-      shops: LookupMap::new(b"shops".try_to_vec().unwrap()),
-      all_shops: UnorderedMap::new(b"all shops".try_to_vec().unwrap()),
-      total_shops: 0,
-      total_products: 0,
-    }
+    let this = Self::internal_new(env::signer_account_id());
+    write_state_version();
+    this
   }
 
   pub fn new_shop(&mut self, name: String, desc: String) -> Shop {
+    self.assert_not_paused();
     let owner = env::signer_account_id();
-    assert!(!self.shops.contains_key(&owner), "Shop already exists");
-    let total_shop = self.total_shops + 1;
+    self.assert_not_banned(&owner);
+    let initial_storage_usage = env::storage_usage();
 
-    let shop = Shop { owner: env::signer_account_id(), name, desc, total_product: 0 };
+    let shop = Shop {
+      owner: env::signer_account_id(),
+      name,
+      desc,
+      total_product: 0,
+      total_orders: 0,
+      completed_orders: 0,
+      cancelled_orders: 0,
+      rating_sum: 0,
+      rating_count: 0,
+      dispute_losses: 0,
+      return_window: DEFAULT_RETURN_WINDOW,
+    };
 
-    self.shops.insert(&owner, &shop);
-    self.all_shops.insert(&total_shop, &shop);
+    self.internal_add_shop(&shop);
+    self.internal_update_storage(&owner, initial_storage_usage);
+    Event::ShopCreate(vec![(&shop).into()]).emit();
 
     shop
   }
 
   pub fn get_shop_by_id(&self, name: AccountId) -> Shop {
-    self.shops.get(&name).unwrap()
+    self.internal_get_shop(&name)
   }
 
-  pub fn get_all_shops(&self) -> Vec<Shop> {
-    let mut all_shop: Vec<Shop> = Vec::new();
-
-    for i in 1..self.all_shops.len() + 1 {
-      if let Some(shop) = self.all_shops.get(&(i as u128)) {
-        all_shop.push(shop);
-      }
-    }
+  pub fn get_all_shops(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Shop> {
+    let (from_index, limit) = pagination(from_index, limit);
+    self.all_shops.values_as_vector().iter().skip(from_index).take(limit).collect()
+  }
 
-    all_shop
+  pub fn get_total_shops(&self) -> u64 {
+    self.all_shops.len()
   }
 
   pub fn new_product(
@@ -95,49 +219,275 @@ impl Contract {
     total_supply: u64,
     price: Balance,
     desc: String,
+    ft_token: Option<AccountId>,
+    category: Option<CategoryId>,
+    tags: Option<Vec<String>>,
   ) -> Product {
+    self.assert_not_paused();
     let owner = env::signer_account_id();
-    assert!(self.shops.contains_key(&owner), "Your Shop not exists");
-    let product =
-      Product { product_id: product_id.clone(), name, total_supply, price, desc, owner: env::signer_account_id() };
-
-    let mut products_set: Vec<Product> = self.products_per_shop.get(&owner).unwrap_or_else(|| Vec::new());
-    products_set.push(product.clone());
+    self.assert_not_banned(&owner);
+    assert!(self.internal_shop(&owner).is_some(), "Your Shop not exists");
+    assert!(product_id.len() <= MAX_PRODUCT_ID_LENGTH, "Product id is longer than {} bytes", MAX_PRODUCT_ID_LENGTH);
+    let initial_storage_usage = env::storage_usage();
+    if let Some(token_id) = ft_token.as_ref() {
+      assert!(self.accepted_tokens.contains(token_id), "Token {} is not accepted", token_id);
+    }
+    if let Some(category_id) = category.as_ref() {
+      self.assert_category_exists(category_id);
+    }
+    let product = Product {
+      product_id,
+      name,
+      total_supply,
+      price,
+      desc,
+      owner: env::signer_account_id(),
+      ft_token,
+      category,
+      tags: normalize_tags(tags.unwrap_or_default()),
+      rating_sum: 0,
+      rating_count: 0,
+      sale: None,
+      variants: Vec::new(),
+      low_stock_threshold: None,
+      delisted: false,
+    };
 
-    self.products_per_shop.insert(&owner, &products_set);
-    self.product_by_id.insert(&product_id, &product);
-    let total = self.total_products + 1;
-    self.products.insert(&total, &product);
+    self.internal_add_product(&product);
+    self.internal_update_storage(&owner, initial_storage_usage);
 
     product
   }
 
-  pub fn get_all_products(&self) -> Vec<Product> {
-    let mut all_products: Vec<Product> = Vec::new();
+  pub fn get_all_products(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Product> {
+    let (from_index, limit) = pagination(from_index, limit);
+    self
+      .products
+      .values_as_vector()
+      .iter()
+      .skip(from_index)
+      .take(limit)
+      .filter(|product| !product.delisted)
+      .map(Product::with_current_price)
+      .collect()
+  }
+
+  // Delisted products are not counted, as they are not listed
+  pub fn get_total_products(&self) -> u64 {
+    self.products.len() - self.total_delisted
+  }
+
+  pub fn update_product(
+    &mut self,
+    product_id: ProductId,
+    name: Option<String>,
+    total_supply: Option<u64>,
+    price: Option<Balance>,
+    desc: Option<String>,
+    category: Option<CategoryId>,
+    tags: Option<Vec<String>>,
+  ) -> Product {
+    self.assert_not_paused();
+    let mut product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+    let initial_storage_usage = env::storage_usage();
+    let old_price = product.price;
 
-    for i in 1..self.products.len() + 1 {
-      if let Some(product) = self.products.get(&(i as u128)) {
-        all_products.push(product);
-      }
+    if let Some(name) = name {
+      product.name = name
+    }
+    if let Some(total_supply) = total_supply {
+      assert!(product.variants.is_empty(), "Update the stock of each variant");
+      product.total_supply = total_supply
+    }
+    if let Some(price) = price {
+      product.price = price
+    }
+    if let Some(desc) = desc {
+      product.desc = desc
+    }
+    if let Some(category_id) = category {
+      self.assert_category_exists(&category_id);
+      product.category = Some(category_id)
+    }
+    if let Some(tags) = tags {
+      product.tags = normalize_tags(tags)
     }
 
-    all_products
+    self.internal_update_product(&product);
+    self.internal_update_storage(&product.owner, initial_storage_usage);
+    self.internal_notify_price_drop(&product, old_price);
+    product
   }
 
-  pub fn update_product(&mut self, product_id: ProductId, price: Balance) -> Product {
-    let mut product = self.get_product_by_id(product_id.clone());
-    product.price = price;
-    self.product_by_id.insert(&product_id, &product);
-    // Product Per Shop
-    // Update Products
+  pub fn delete_product(&mut self, product_id: ProductId) -> Product {
+    self.assert_not_paused();
+    let product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+    let initial_storage_usage = env::storage_usage();
+
+    self.internal_remove_product(&product_id);
+    self.internal_update_storage(&product.owner, initial_storage_usage);
     product
   }
 
   pub fn get_product_by_id(&self, product_id: ProductId) -> Product {
-    self.product_by_id.get(&product_id).unwrap()
+    self.internal_get_product(&product_id).with_current_price()
+  }
+
+  pub fn get_products_by_owner(&self, owner: AccountId, from_index: Option<u64>, limit: Option<u64>) -> Vec<Product> {
+    let (from_index, limit) = pagination(from_index, limit);
+    match self.products_per_shop.get(&owner) {
+      Some(product_ids) => product_ids
+        .as_vector()
+        .iter()
+        .skip(from_index)
+        .take(limit)
+        .map(|product_id| self.internal_get_product(&product_id))
+        .filter(|product| !product.delisted)
+        .map(Product::with_current_price)
+        .collect(),
+      None => Vec::new(),
+    }
   }
 
-  pub fn get_products_by_owner(&self, owner: AccountId) -> Vec<Product> {
-    self.products_per_shop.get(&owner).unwrap_or_else(|| Vec::new())
+  pub fn get_total_products_by_owner(&self, owner: AccountId) -> u64 {
+    let total = self.products_per_shop.get(&owner).map(|product_ids| product_ids.len()).unwrap_or(0);
+    total - self.delisted_per_shop.get(&owner).unwrap_or(0)
+  }
+
+  #[payable]
+  pub fn buy_product(
+    &mut self,
+    product_id: ProductId,
+    quantity: u64,
+    coupon_code: Option<String>,
+    sku: Option<String>,
+  ) -> Order {
+    self.assert_not_paused();
+    // The deposit comes from the predecessor, which is not the signer when a contract buys
+    let buyer = env::predecessor_account_id();
+    let product = self.internal_get_product(&product_id);
+    assert!(product.ft_token.is_none(), "Product is priced in a fungible token, pay with ft_transfer_call");
+    let mut total_price = self.internal_check_purchase(&buyer, &product, sku.as_ref(), quantity);
+    if let Some(code) = coupon_code {
+      total_price -= self.internal_apply_coupon(&buyer, &product, &code, total_price);
+    }
+
+    let deposit = env::attached_deposit();
+    assert!(deposit >= total_price, "Not enough money");
+
+    let order = self.internal_purchase(&buyer, &product_id, sku, quantity, total_price);
+    // Refund
+    if deposit > total_price {
+      Promise::new(buyer).transfer(deposit - total_price);
+    }
+
+    order
   }
 }
+
+impl Contract {
+  pub(crate) fn internal_new(platform_name: AccountId) -> Self {
+    let mut this = Self {
+      platform_name,
+      products_per_shop: LookupMap::new(StorageKey::ProductPerOwnerKey.try_to_vec().unwrap()),
+      product_by_id: LookupMap::new(b"product by id".try_to_vec().unwrap()),
+      products: UnorderedMap::new(b"products".try_to_vec().unwrap()),
+      shops: LookupMap::new(b"shops".try_to_vec().unwrap()),
+      all_shops: UnorderedMap::new(b"all shops".try_to_vec().unwrap()),
+      total_shops: 0,
+      total_products: 0,
+      orders: LookupMap::new(StorageKey::OrdersKey.try_to_vec().unwrap()),
+      orders_per_buyer: LookupMap::new(StorageKey::OrdersPerBuyerKey.try_to_vec().unwrap()),
+      orders_per_shop: LookupMap::new(StorageKey::OrdersPerShopKey.try_to_vec().unwrap()),
+      total_orders: 0,
+      escrow_timeout: DEFAULT_ESCROW_TIMEOUT,
+      total_escrow: 0,
+      platform_fee: 0,
+      platform_balance: 0,
+      total_platform_fees: 0,
+      carts: LookupMap::new(StorageKey::ChargedCartsKey.try_to_vec().unwrap()),
+      accepted_tokens: UnorderedSet::new(StorageKey::AcceptedTokensKey.try_to_vec().unwrap()),
+      platform_ft_balances: LookupMap::new(StorageKey::PlatformFtBalancesKey.try_to_vec().unwrap()),
+      storage_accounts: LookupMap::new(StorageKey::StorageAccountsKey.try_to_vec().unwrap()),
+      account_storage_usage: 0,
+      product_index: LookupMap::new(StorageKey::ProductIndexKey.try_to_vec().unwrap()),
+      shop_index: LookupMap::new(StorageKey::ShopIndexKey.try_to_vec().unwrap()),
+      pending_shop_transfers: LookupMap::new(StorageKey::PendingShopTransfersKey.try_to_vec().unwrap()),
+      categories: UnorderedMap::new(StorageKey::CategoriesKey.try_to_vec().unwrap()),
+      products_per_category: LookupMap::new(StorageKey::ProductsPerCategoryKey.try_to_vec().unwrap()),
+      products_per_tag: LookupMap::new(StorageKey::ProductsPerTagKey.try_to_vec().unwrap()),
+      product_name_index: TreeMap::new(StorageKey::ProductNameIndexKey.try_to_vec().unwrap()),
+      shop_name_index: TreeMap::new(StorageKey::ShopNameIndexKey.try_to_vec().unwrap()),
+      reviews_per_product: LookupMap::new(StorageKey::ReviewsPerProductKey.try_to_vec().unwrap()),
+      coupons: LookupMap::new(StorageKey::CouponsKey.try_to_vec().unwrap()),
+      coupons_per_shop: LookupMap::new(StorageKey::CouponsPerShopKey.try_to_vec().unwrap()),
+      coupon_uses: LookupMap::new(StorageKey::CouponUsesKey.try_to_vec().unwrap()),
+      reservations: LookupMap::new(StorageKey::ReservationsKey.try_to_vec().unwrap()),
+      roles: UnorderedMap::new(StorageKey::RolesKey.try_to_vec().unwrap()),
+      paused: false,
+      reports: LookupMap::new(StorageKey::ReportsKey.try_to_vec().unwrap()),
+      open_reports: TreeMap::new(StorageKey::OpenReportsByIdKey.try_to_vec().unwrap()),
+      total_reports: 0,
+      banned_accounts: UnorderedSet::new(StorageKey::BannedAccountsKey.try_to_vec().unwrap()),
+      disputes: LookupMap::new(StorageKey::DisputesKey.try_to_vec().unwrap()),
+      open_disputes: UnorderedSet::new(StorageKey::OpenDisputesKey.try_to_vec().unwrap()),
+      returns: LookupMap::new(StorageKey::ReturnsKey.try_to_vec().unwrap()),
+      ft_claims: LookupMap::new(StorageKey::FtClaimsKey.try_to_vec().unwrap()),
+      total_delisted: 0,
+      delisted_per_shop: LookupMap::new(StorageKey::DelistedPerShopKey.try_to_vec().unwrap()),
+      wishlists: LookupMap::new(StorageKey::WishlistsKey.try_to_vec().unwrap()),
+      wishlisted_by: LookupMap::new(StorageKey::WishlistedByKey.try_to_vec().unwrap()),
+      delisted_per_category: LookupMap::new(StorageKey::DelistedPerCategoryKey.try_to_vec().unwrap()),
+      delisted_per_tag: LookupMap::new(StorageKey::DelistedPerTagKey.try_to_vec().unwrap()),
+    };
+    this.measure_account_storage_usage();
+    this
+  }
+
+  // Validate a purchase and return its total price, items on sale are charged the sale price.
+  // Stock reserved by other buyers can not be bought.
+  pub(crate) fn internal_check_purchase(
+    &self,
+    buyer: &AccountId,
+    product: &Product,
+    sku: Option<&String>,
+    quantity: u64,
+  ) -> Balance {
+    assert!(quantity > 0, "Quantity must be greater than 0");
+    assert_ne!(buyer, &product.owner, "You can not buy your own product");
+    assert!(!product.delisted, "Product {} has been delisted", product.product_id);
+    let stock = self.internal_available_stock(product, sku, Some(buyer));
+    assert!(stock > 0, "Product {} is sold out", product.product_id);
+    assert!(stock >= quantity, "Not enough {} in stock", product.product_id);
+    product.purchase_price(sku, quantity)
+  }
+
+  // Take the items out of stock and create a paid order, the payment stays in the contract
+  // until the buyer confirms receipt
+  pub(crate) fn internal_purchase(
+    &mut self,
+    buyer: &AccountId,
+    product_id: &ProductId,
+    sku: Option<String>,
+    quantity: u64,
+    total_price: Balance,
+  ) -> Order {
+    let mut product = self.internal_get_product(product_id);
+    let price = product.purchase_price(sku.as_ref(), quantity) / quantity as u128;
+    product.sell(sku.as_ref(), quantity);
+    self.internal_update_product(&product);
+    self.internal_release_reservation(product_id, sku.as_ref(), buyer);
+
+    let mut order = self.internal_create_order(buyer, &product, sku, quantity, price, total_price);
+    Event::Purchase(vec![(&order).into()]).emit();
+    Event::OrderStatus(vec![OrderStatusData { order_id: U128(order.order_id), status: order.status }]).emit();
+    self.internal_hold_escrow(&mut order);
+    order
+  }
+}
+
+pub(crate) fn pagination(from_index: Option<u64>, limit: Option<u64>) -> (usize, usize) {
+  (from_index.unwrap_or(0) as usize, limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+}
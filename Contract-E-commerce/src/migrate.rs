@@ -0,0 +1,682 @@
+use std::collections::BTreeMap;
+
+use crate::*;
+
+// Stored next to the contract state, states written before versioning have no version
+const STATE_VERSION_KEY: &[u8] = b"VERSION";
+pub const STATE_VERSION: u8 = 3;
+
+// Layout of the first deployed contract, before orders were added
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+  pub platform_name: AccountId,
+  pub products_per_shop: UnorderedMap<AccountId, Vec<ProductV1>>,
+  pub product_by_id: LookupMap<ProductId, ProductV1>,
+  pub products: UnorderedMap<u128, ProductV1>,
+  pub shops: LookupMap<AccountId, ShopV1>,
+  pub all_shops: UnorderedMap<u128, ShopV1>,
+  pub total_shops: u128,
+  pub total_products: u128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct ProductV1 {
+  pub product_id: ProductId,
+  pub name: String,
+  pub total_supply: u64,
+  pub price: Balance,
+  pub desc: String,
+  pub owner: AccountId,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ShopV1 {
+  pub owner: AccountId,
+  pub name: String,
+  pub desc: String,
+  pub total_product: u64,
+}
+
+// Layout of version 2, before roles, moderation, disputes, returns and wishlists
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV2 {
+  pub platform_name: AccountId,
+  pub products_per_shop: UnorderedMap<AccountId, Vec<ProductV2>>,
+  pub product_by_id: LookupMap<ProductId, ProductV2>,
+  pub products: UnorderedMap<u128, ProductV2>,
+  pub shops: LookupMap<AccountId, ShopV2>,
+  pub all_shops: UnorderedMap<u128, ShopV2>,
+  pub total_shops: u128,
+  pub total_products: u128,
+  pub orders: LookupMap<OrderId, OrderV2>,
+  pub orders_per_buyer: LookupMap<AccountId, Vec<OrderId>>,
+  pub orders_per_shop: LookupMap<AccountId, Vec<OrderId>>,
+  pub total_orders: u128,
+  pub escrow_timeout: u64,
+  pub total_escrow: Balance,
+  pub platform_fee: u32,
+  pub platform_balance: Balance,
+  pub total_platform_fees: Balance,
+  pub carts: LookupMap<AccountId, Vec<CartItem>>,
+  pub accepted_tokens: UnorderedSet<AccountId>,
+  pub platform_ft_balances: LookupMap<AccountId, Balance>,
+  pub storage_accounts: LookupMap<AccountId, StorageAccount>,
+  pub account_storage_usage: StorageUsage,
+  pub product_index: LookupMap<ProductId, u128>,
+  pub shop_index: LookupMap<AccountId, u128>,
+  pub pending_shop_transfers: LookupMap<AccountId, AccountId>,
+  pub categories: UnorderedMap<CategoryId, Category>,
+  pub products_per_category: LookupMap<CategoryId, UnorderedSet<ProductId>>,
+  pub products_per_tag: LookupMap<String, UnorderedSet<ProductId>>,
+  pub product_name_index: TreeMap<String, UnorderedSet<ProductId>>,
+  pub shop_name_index: TreeMap<String, UnorderedSet<AccountId>>,
+  pub reviews_per_product: LookupMap<ProductId, Vector<Review>>,
+  pub coupons: LookupMap<Base58CryptoHash, Coupon>,
+  pub coupons_per_shop: LookupMap<AccountId, Vec<Base58CryptoHash>>,
+  pub coupon_uses: LookupMap<(Base58CryptoHash, AccountId), u32>,
+  pub reservations: LookupMap<ProductId, Vec<StockReservation>>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct ProductV2 {
+  pub product_id: ProductId,
+  pub name: String,
+  pub total_supply: u64,
+  pub price: Balance,
+  pub desc: String,
+  pub owner: AccountId,
+  pub ft_token: Option<AccountId>,
+  pub category: Option<CategoryId>,
+  pub tags: Vec<String>,
+  pub rating_sum: u64,
+  pub rating_count: u64,
+  pub sale: Option<Sale>,
+  pub variants: Vec<Variant>,
+  pub low_stock_threshold: Option<u64>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ShopV2 {
+  pub owner: AccountId,
+  pub name: String,
+  pub desc: String,
+  pub total_product: u64,
+  pub total_orders: u64,
+  pub completed_orders: u64,
+  pub cancelled_orders: u64,
+  pub rating_sum: u64,
+  pub rating_count: u64,
+  pub dispute_losses: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OrderV2 {
+  pub order_id: OrderId,
+  pub buyer: AccountId,
+  pub seller: AccountId,
+  pub product_id: ProductId,
+  pub sku: Option<String>,
+  pub quantity: u64,
+  pub price: Balance,
+  pub amount: Balance,
+  pub fee: Balance,
+  pub ft_token: Option<AccountId>,
+  pub status: OrderStatusV2,
+  pub escrow_status: EscrowStatus,
+  pub release_at: Timestamp,
+  pub claim: Option<String>,
+  pub reviewed: bool,
+  pub created_at: Timestamp,
+  pub updated_at: Timestamp,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub enum OrderStatusV2 {
+  Pending,
+  Paid,
+  Shipped,
+  Delivered,
+  Cancelled,
+  Refunded,
+}
+
+// When the layout of `Contract` changes, keep the old one here as a new variant and bump `STATE_VERSION`
+pub enum VersionedContract {
+  V1(Box<ContractV1>),
+  V2(Box<ContractV2>),
+  V3(Box<Contract>),
+}
+
+impl VersionedContract {
+  pub fn read() -> Self {
+    match env::storage_read(STATE_VERSION_KEY).as_deref() {
+      None => Self::V1(Box::new(env::state_read().expect("Contract is not initialized"))),
+      Some([2]) => Self::V2(Box::new(env::state_read().expect("Contract is not initialized"))),
+      Some([STATE_VERSION]) => Self::V3(Box::new(env::state_read().expect("Contract is not initialized"))),
+      Some(_) => env::panic_str("Unknown state version"),
+    }
+  }
+
+  // `shop_owners` are shops to recover that can not be found from the old state, see `ContractV1::migrate`
+  pub fn into_current(self, shop_owners: Vec<AccountId>) -> Contract {
+    match self {
+      Self::V1(contract) => contract.migrate(shop_owners),
+      Self::V2(contract) => contract.migrate(),
+      Self::V3(contract) => *contract,
+    }
+  }
+}
+
+impl ContractV1 {
+  // The old collections use the same prefixes as the new ones, so everything is read out,
+  // removed and added again through the repository methods to build every index.
+  // The v1 contract kept every shop and product at index 1 of `all_shops` and `products`,
+  // so shops are found from their products and the last shop; shops without products
+  // have to be passed in `shop_owners`. Shops that are not found stay in `shops` in the v1 layout,
+  // `internal_shop` leaves them out and they are replaced when their owner opens a new shop.
+  pub fn migrate(mut self, shop_owners: Vec<AccountId>) -> Contract {
+    let mut products = BTreeMap::new();
+    let mut owners: Vec<AccountId> = shop_owners;
+    for (owner, shop_products) in self.products_per_shop.iter() {
+      owners.push(owner);
+      for product in shop_products {
+        // `product_by_id` holds the last product listed with an id
+        if let Some(product) = self.product_by_id.get(&product.product_id) {
+          products.insert(product.product_id.clone(), product);
+        }
+      }
+    }
+    owners.extend(self.all_shops.values().map(|shop| shop.owner));
+    owners.sort();
+    owners.dedup();
+    let shops: Vec<ShopV1> = owners.iter().filter_map(|owner| self.shops.remove(owner)).collect();
+
+    for product_id in products.keys() {
+      self.product_by_id.remove(product_id);
+    }
+    self.products_per_shop.clear();
+    self.products.clear();
+    self.all_shops.clear();
+
+    let mut contract = Contract::internal_new(self.platform_name);
+    for shop in shops {
+      contract.internal_add_shop(&Shop {
+        owner: shop.owner,
+        name: shop.name,
+        desc: shop.desc,
+        total_product: 0,
+        total_orders: 0,
+        completed_orders: 0,
+        cancelled_orders: 0,
+        rating_sum: 0,
+        rating_count: 0,
+        dispute_losses: 0,
+        return_window: DEFAULT_RETURN_WINDOW,
+      });
+    }
+    for product in products.into_values() {
+      if !contract.shops.contains_key(&product.owner) {
+        continue;
+      }
+      contract.internal_add_product(&Product {
+        product_id: product.product_id,
+        name: product.name,
+        total_supply: product.total_supply,
+        price: product.price,
+        desc: product.desc,
+        owner: product.owner,
+        ft_token: None,
+        category: None,
+        tags: Vec::new(),
+        rating_sum: 0,
+        rating_count: 0,
+        sale: None,
+        variants: Vec::new(),
+        low_stock_threshold: None,
+        delisted: false,
+      });
+    }
+    contract
+  }
+}
+
+impl ContractV2 {
+  // Products, shops and orders have new fields and the products of a shop are now a set of ids.
+  // The collections keep their prefixes, so their records are rewritten in place with `insert_raw`,
+  // which does not read the old record with the new type.
+  pub fn migrate(mut self) -> Contract {
+    for (index, shop) in self.all_shops.to_vec() {
+      let shop = Shop {
+        owner: shop.owner,
+        name: shop.name,
+        desc: shop.desc,
+        total_product: shop.total_product,
+        total_orders: shop.total_orders,
+        completed_orders: shop.completed_orders,
+        cancelled_orders: shop.cancelled_orders,
+        rating_sum: shop.rating_sum,
+        rating_count: shop.rating_count,
+        dispute_losses: shop.dispute_losses,
+        return_window: DEFAULT_RETURN_WINDOW,
+      };
+      self.shops.insert_raw(&shop.owner.try_to_vec().unwrap(), &shop.try_to_vec().unwrap());
+      self.all_shops.insert_raw(&index.try_to_vec().unwrap(), &shop.try_to_vec().unwrap());
+    }
+
+    let mut products = Vec::new();
+    for (index, product) in self.products.to_vec() {
+      let product = Product {
+        product_id: product.product_id,
+        name: product.name,
+        total_supply: product.total_supply,
+        price: product.price,
+        desc: product.desc,
+        owner: product.owner,
+        ft_token: product.ft_token,
+        category: product.category,
+        tags: product.tags,
+        rating_sum: product.rating_sum,
+        rating_count: product.rating_count,
+        sale: product.sale,
+        variants: product.variants,
+        low_stock_threshold: product.low_stock_threshold,
+        delisted: false,
+      };
+      self.products.insert_raw(&index.try_to_vec().unwrap(), &product.try_to_vec().unwrap());
+      self.product_by_id.insert_raw(&product.product_id.try_to_vec().unwrap(), &product.try_to_vec().unwrap());
+      // Reservations were not charged to their account before, releasing them would free bytes it never paid for
+      self.reservations.remove_raw(&product.product_id.try_to_vec().unwrap());
+      products.push((index, product));
+    }
+    // Its entries are under other keys than the ones of the new map with the same prefix
+    self.products_per_shop.clear();
+
+    for order_id in 1..=self.total_orders {
+      let Some(order) = self.orders.get(&order_id) else { continue };
+      let order = Order {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        product_id: order.product_id,
+        sku: order.sku,
+        quantity: order.quantity,
+        price: order.price,
+        amount: order.amount,
+        fee: order.fee,
+        refunded: match order.escrow_status {
+          EscrowStatus::Refunded => order.amount,
+          _ => 0,
+        },
+        ft_token: order.ft_token,
+        // The escrow timeout now starts when the order ships
+        release_at: match order.status {
+          OrderStatusV2::Pending | OrderStatusV2::Paid => 0,
+          _ => order.release_at,
+        },
+        delivered_at: match order.status {
+          OrderStatusV2::Delivered => order.updated_at,
+          _ => 0,
+        },
+        // Orders were paid in the call that created them, so none was left pending or cancelled
+        status: match order.status {
+          OrderStatusV2::Pending | OrderStatusV2::Paid => OrderStatus::Paid,
+          OrderStatusV2::Shipped => OrderStatus::Shipped,
+          OrderStatusV2::Delivered => OrderStatus::Delivered,
+          OrderStatusV2::Cancelled | OrderStatusV2::Refunded => OrderStatus::Refunded,
+        },
+        escrow_status: order.escrow_status,
+        return_window: DEFAULT_RETURN_WINDOW,
+        claim: order.claim,
+        reviewed: order.reviewed,
+        created_at: order.created_at,
+        updated_at: order.updated_at,
+      };
+      self.orders.insert_raw(&order_id.try_to_vec().unwrap(), &order.try_to_vec().unwrap());
+    }
+
+    let mut contract = Contract {
+      platform_name: self.platform_name,
+      products_per_shop: LookupMap::new(StorageKey::ProductPerOwnerKey.try_to_vec().unwrap()),
+      product_by_id: retype(&self.product_by_id),
+      products: retype(&self.products),
+      shops: retype(&self.shops),
+      all_shops: retype(&self.all_shops),
+      total_shops: self.total_shops,
+      total_products: self.total_products,
+      orders: retype(&self.orders),
+      orders_per_buyer: self.orders_per_buyer,
+      orders_per_shop: self.orders_per_shop,
+      total_orders: self.total_orders,
+      escrow_timeout: self.escrow_timeout,
+      total_escrow: self.total_escrow,
+      platform_fee: self.platform_fee,
+      platform_balance: self.platform_balance,
+      total_platform_fees: self.total_platform_fees,
+      // Carts were not charged to their account before, they are left behind for new ones that are
+      carts: LookupMap::new(StorageKey::ChargedCartsKey.try_to_vec().unwrap()),
+      accepted_tokens: self.accepted_tokens,
+      platform_ft_balances: self.platform_ft_balances,
+      storage_accounts: self.storage_accounts,
+      account_storage_usage: self.account_storage_usage,
+      product_index: self.product_index,
+      shop_index: self.shop_index,
+      pending_shop_transfers: self.pending_shop_transfers,
+      categories: self.categories,
+      products_per_category: self.products_per_category,
+      products_per_tag: self.products_per_tag,
+      product_name_index: self.product_name_index,
+      shop_name_index: self.shop_name_index,
+      reviews_per_product: self.reviews_per_product,
+      coupons: self.coupons,
+      coupons_per_shop: self.coupons_per_shop,
+      coupon_uses: self.coupon_uses,
+      reservations: self.reservations,
+      roles: UnorderedMap::new(StorageKey::RolesKey.try_to_vec().unwrap()),
+      paused: false,
+      reports: LookupMap::new(StorageKey::ReportsKey.try_to_vec().unwrap()),
+      open_reports: TreeMap::new(StorageKey::OpenReportsByIdKey.try_to_vec().unwrap()),
+      total_reports: 0,
+      banned_accounts: UnorderedSet::new(StorageKey::BannedAccountsKey.try_to_vec().unwrap()),
+      disputes: LookupMap::new(StorageKey::DisputesKey.try_to_vec().unwrap()),
+      open_disputes: UnorderedSet::new(StorageKey::OpenDisputesKey.try_to_vec().unwrap()),
+      returns: LookupMap::new(StorageKey::ReturnsKey.try_to_vec().unwrap()),
+      ft_claims: LookupMap::new(StorageKey::FtClaimsKey.try_to_vec().unwrap()),
+      total_delisted: 0,
+      delisted_per_shop: LookupMap::new(StorageKey::DelistedPerShopKey.try_to_vec().unwrap()),
+      wishlists: LookupMap::new(StorageKey::WishlistsKey.try_to_vec().unwrap()),
+      wishlisted_by: LookupMap::new(StorageKey::WishlistedByKey.try_to_vec().unwrap()),
+      delisted_per_category: LookupMap::new(StorageKey::DelistedPerCategoryKey.try_to_vec().unwrap()),
+      delisted_per_tag: LookupMap::new(StorageKey::DelistedPerTagKey.try_to_vec().unwrap()),
+    };
+
+    // Index the products in the set of their shop
+    for (index, product) in products {
+      contract.internal_store_product(&product, index);
+    }
+    contract
+  }
+}
+
+// Collections only store their prefix and length, so they can be read back with the new type of their values
+pub(crate) fn retype<T: BorshSerialize, U: BorshDeserialize>(collection: &T) -> U {
+  U::try_from_slice(&collection.try_to_vec().unwrap()).unwrap()
+}
+
+#[near_bindgen]
+impl Contract {
+  // Upgrade the state written by any previous version of the contract, call it with the new code deployed
+  #[private]
+  #[init(ignore_state)]
+  pub fn migrate(shop_owners: Option<Vec<AccountId>>) -> Self {
+    let contract = VersionedContract::read().into_current(shop_owners.unwrap_or_default());
+    write_state_version();
+    contract
+  }
+
+  // The version the state was written with, states written before versioning are version 1
+  pub fn get_state_version(&self) -> u8 {
+    match env::storage_read(STATE_VERSION_KEY).as_deref() {
+      Some([version]) => *version,
+      _ => 1,
+    }
+  }
+}
+
+pub(crate) fn write_state_version() {
+  env::storage_write(STATE_VERSION_KEY, &[STATE_VERSION]);
+}
+
+#[cfg(test)]
+mod tests {
+  use near_contract_standards::storage_management::StorageManagement;
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  fn product_v1(product_id: &str, owner: AccountId) -> ProductV1 {
+    ProductV1 {
+      product_id: product_id.to_string(),
+      name: format!("Quan {}", product_id),
+      total_supply: 10,
+      price: 100,
+      desc: "desc".to_string(),
+      owner,
+    }
+  }
+
+  fn shop_v1(owner: AccountId) -> ShopV1 {
+    ShopV1 { owner: owner.clone(), name: format!("Shop {}", owner), desc: "desc".to_string(), total_product: 0 }
+  }
+
+  // Write the state the way the v1 contract did, with everything at index 1
+  fn write_v1_state() {
+    let mut contract = ContractV1 {
+      platform_name: accounts(0),
+      products_per_shop: UnorderedMap::new(StorageKey::ProductPerOwnerKey.try_to_vec().unwrap()),
+      product_by_id: LookupMap::new(b"product by id".try_to_vec().unwrap()),
+      products: UnorderedMap::new(b"products".try_to_vec().unwrap()),
+      shops: LookupMap::new(b"shops".try_to_vec().unwrap()),
+      all_shops: UnorderedMap::new(b"all shops".try_to_vec().unwrap()),
+      total_shops: 0,
+      total_products: 0,
+    };
+    for owner in [accounts(1), accounts(2), accounts(3)] {
+      contract.shops.insert(&owner, &shop_v1(owner.clone()));
+      contract.all_shops.insert(&1, &shop_v1(owner));
+    }
+    let products = vec![product_v1("p1", accounts(1)), product_v1("p2", accounts(1))];
+    for product in products.iter() {
+      contract.product_by_id.insert(&product.product_id, product);
+      contract.products.insert(&1, product);
+    }
+    contract.products_per_shop.insert(&accounts(1), &products);
+    env::state_write(&contract);
+  }
+
+  #[test]
+  fn migrate_v1_state() {
+    set_context(accounts(0), 0);
+    write_v1_state();
+
+    let mut contract = Contract::migrate(None);
+    assert_eq!(contract.get_state_version(), STATE_VERSION);
+    assert_eq!(contract.get_total_shops(), 2);
+    assert_eq!(contract.get_shop_by_id(accounts(1)).total_product, 2);
+    assert_eq!(contract.get_shop_by_id(accounts(3)).name, format!("Shop {}", accounts(3)));
+    assert_eq!(contract.get_total_products(), 2);
+    assert_eq!(contract.get_product_by_id("p2".to_string()).price, 100);
+    assert_eq!(contract.get_products_by_owner(accounts(1), None, None).len(), 2);
+    assert_eq!(contract.search_products("quan".to_string(), None, None).len(), 2);
+
+    // Shops of the v1 contract did not pay for storage, they register before making changes
+    set_context(accounts(1), ONE_NEAR);
+    contract.storage_deposit(None, None);
+    contract.update_shop(None, Some("new desc".to_string()));
+    assert_eq!(contract.get_shop_by_id(accounts(1)).desc, "new desc");
+
+    // The shop of `accounts(2)` was not found, its owner can open a new one
+    set_context(accounts(2), ONE_NEAR);
+    contract.storage_deposit(None, None);
+    contract.new_shop("Shop 2".to_string(), "desc".to_string());
+    assert_eq!(contract.get_shop_by_id(accounts(2)).name, "Shop 2");
+    assert_eq!(contract.get_total_shops(), 3);
+  }
+
+  #[test]
+  #[should_panic(expected = "Shop not found")]
+  fn migrate_leaves_out_shops_not_found() {
+    set_context(accounts(0), 0);
+    write_v1_state();
+
+    let contract = Contract::migrate(None);
+    contract.get_shop_by_id(accounts(2));
+  }
+
+  #[test]
+  fn migrate_recovers_shops_without_products() {
+    set_context(accounts(0), 0);
+    write_v1_state();
+
+    let contract = Contract::migrate(Some(vec![accounts(2)]));
+    assert_eq!(contract.get_total_shops(), 3);
+    assert_eq!(contract.get_shop_by_id(accounts(2)).total_product, 0);
+  }
+
+  // Write the state of version 2 with a shop of `accounts(1)` selling "p1" and a paid order 1 of `accounts(2)`
+  fn write_v2_state() {
+    let mut contract = ContractV2 {
+      platform_name: accounts(0),
+      products_per_shop: UnorderedMap::new(StorageKey::ProductPerOwnerKey.try_to_vec().unwrap()),
+      product_by_id: LookupMap::new(b"product by id".try_to_vec().unwrap()),
+      products: UnorderedMap::new(b"products".try_to_vec().unwrap()),
+      shops: LookupMap::new(b"shops".try_to_vec().unwrap()),
+      all_shops: UnorderedMap::new(b"all shops".try_to_vec().unwrap()),
+      total_shops: 1,
+      total_products: 1,
+      orders: LookupMap::new(StorageKey::OrdersKey.try_to_vec().unwrap()),
+      orders_per_buyer: LookupMap::new(StorageKey::OrdersPerBuyerKey.try_to_vec().unwrap()),
+      orders_per_shop: LookupMap::new(StorageKey::OrdersPerShopKey.try_to_vec().unwrap()),
+      total_orders: 1,
+      escrow_timeout: DEFAULT_ESCROW_TIMEOUT,
+      total_escrow: 300,
+      platform_fee: 0,
+      platform_balance: 0,
+      total_platform_fees: 0,
+      carts: LookupMap::new(StorageKey::CartsKey.try_to_vec().unwrap()),
+      accepted_tokens: UnorderedSet::new(StorageKey::AcceptedTokensKey.try_to_vec().unwrap()),
+      platform_ft_balances: LookupMap::new(StorageKey::PlatformFtBalancesKey.try_to_vec().unwrap()),
+      storage_accounts: LookupMap::new(StorageKey::StorageAccountsKey.try_to_vec().unwrap()),
+      account_storage_usage: 100,
+      product_index: LookupMap::new(StorageKey::ProductIndexKey.try_to_vec().unwrap()),
+      shop_index: LookupMap::new(StorageKey::ShopIndexKey.try_to_vec().unwrap()),
+      pending_shop_transfers: LookupMap::new(StorageKey::PendingShopTransfersKey.try_to_vec().unwrap()),
+      categories: UnorderedMap::new(StorageKey::CategoriesKey.try_to_vec().unwrap()),
+      products_per_category: LookupMap::new(StorageKey::ProductsPerCategoryKey.try_to_vec().unwrap()),
+      products_per_tag: LookupMap::new(StorageKey::ProductsPerTagKey.try_to_vec().unwrap()),
+      product_name_index: TreeMap::new(StorageKey::ProductNameIndexKey.try_to_vec().unwrap()),
+      shop_name_index: TreeMap::new(StorageKey::ShopNameIndexKey.try_to_vec().unwrap()),
+      reviews_per_product: LookupMap::new(StorageKey::ReviewsPerProductKey.try_to_vec().unwrap()),
+      coupons: LookupMap::new(StorageKey::CouponsKey.try_to_vec().unwrap()),
+      coupons_per_shop: LookupMap::new(StorageKey::CouponsPerShopKey.try_to_vec().unwrap()),
+      coupon_uses: LookupMap::new(StorageKey::CouponUsesKey.try_to_vec().unwrap()),
+      reservations: LookupMap::new(StorageKey::ReservationsKey.try_to_vec().unwrap()),
+    };
+    let shop = ShopV2 {
+      owner: accounts(1),
+      name: "Shop".to_string(),
+      desc: "desc".to_string(),
+      total_product: 1,
+      total_orders: 1,
+      completed_orders: 0,
+      cancelled_orders: 0,
+      rating_sum: 0,
+      rating_count: 0,
+      dispute_losses: 0,
+    };
+    contract.shops.insert(&accounts(1), &shop);
+    contract.all_shops.insert(&1, &shop);
+    contract.shop_index.insert(&accounts(1), &1);
+    contract.storage_accounts.insert(&accounts(1), &StorageAccount { total: ONE_NEAR, used: 500 });
+
+    let product = ProductV2 {
+      product_id: "p1".to_string(),
+      name: "Quan".to_string(),
+      total_supply: 7,
+      price: 100,
+      desc: "desc".to_string(),
+      owner: accounts(1),
+      ft_token: None,
+      category: None,
+      tags: Vec::new(),
+      rating_sum: 0,
+      rating_count: 0,
+      sale: None,
+      variants: Vec::new(),
+      low_stock_threshold: None,
+    };
+    contract.products.insert(&1, &product);
+    contract.product_by_id.insert(&product.product_id, &product);
+    contract.product_index.insert(&product.product_id, &1);
+    contract.products_per_shop.insert(&accounts(1), &vec![product]);
+
+    let order = OrderV2 {
+      order_id: 1,
+      buyer: accounts(2),
+      seller: accounts(1),
+      product_id: "p1".to_string(),
+      sku: None,
+      quantity: 3,
+      price: 100,
+      amount: 300,
+      fee: 0,
+      ft_token: None,
+      status: OrderStatusV2::Paid,
+      escrow_status: EscrowStatus::Held,
+      release_at: DEFAULT_ESCROW_TIMEOUT,
+      claim: None,
+      reviewed: false,
+      created_at: 0,
+      updated_at: 0,
+    };
+    contract.orders.insert(&1, &order);
+    contract.orders_per_buyer.insert(&accounts(2), &vec![1]);
+    contract.orders_per_shop.insert(&accounts(1), &vec![1]);
+    contract.storage_accounts.insert(&accounts(3), &StorageAccount { total: ONE_NEAR, used: 0 });
+    let reservation = StockReservation { account_id: accounts(3), sku: None, quantity: 2, expires_at: u64::MAX };
+    contract.reservations.insert(&"p1".to_string(), &vec![reservation]);
+    env::state_write(&contract);
+    env::storage_write(STATE_VERSION_KEY, &[2]);
+  }
+
+  #[test]
+  fn migrate_v2_state() {
+    set_context(accounts(0), 0);
+    write_v2_state();
+    assert_eq!(env::storage_read(STATE_VERSION_KEY), Some(vec![2]));
+
+    let mut contract = Contract::migrate(None);
+    assert_eq!(contract.get_state_version(), STATE_VERSION);
+    assert_eq!(contract.get_shop_by_id(accounts(1)).return_window, DEFAULT_RETURN_WINDOW);
+    assert_eq!(contract.get_all_shops(None, None).len(), 1);
+    assert_eq!(contract.get_total_products(), 1);
+    assert_eq!(contract.get_total_products_by_owner(accounts(1)), 1);
+    assert_eq!(contract.get_products_by_owner(accounts(1), None, None)[0].total_supply, 7);
+    assert_eq!(contract.search_products("quan".to_string(), None, None).len(), 1);
+    let order = contract.get_order(1);
+    assert_eq!((order.release_at, order.refunded, order.return_window), (0, 0, DEFAULT_RETURN_WINDOW));
+    // The reservation was never charged, so it is dropped
+    assert!(contract.get_reservations("p1".to_string()).is_empty());
+    assert_eq!(contract.get_available_stock("p1".to_string(), None), 7);
+
+    // The paid order goes on with the new escrow rules
+    set_context(accounts(1), 0);
+    let order = contract.ship_order(1);
+    assert_eq!(order.release_at, DEFAULT_ESCROW_TIMEOUT);
+    set_context(accounts(2), 0);
+    contract.confirm_delivery(1);
+    assert_eq!(transfers(), vec![(accounts(1), 300)]);
+
+    // Products are moved and indexed as new ones
+    set_context(accounts(1), 0);
+    contract.new_product("p2".to_string(), "Ao".to_string(), 5, 50, "desc".to_string(), None, None, None);
+    assert_eq!(contract.get_total_products_by_owner(accounts(1)), 2);
+    contract.delete_product("p1".to_string());
+    assert_eq!(contract.get_all_products(None, None).len(), 1);
+  }
+
+  #[test]
+  fn migrate_current_state() {
+    set_context(accounts(0), 0);
+    let mut contract = Contract::init();
+    set_context(accounts(1), ONE_NEAR);
+    contract.storage_deposit(None, None);
+    contract.new_shop("shop".to_string(), "desc".to_string());
+    env::state_write(&contract);
+
+    set_context(accounts(0), 0);
+    let contract = Contract::migrate(None);
+    assert_eq!(contract.get_total_shops(), 1);
+    assert_eq!(contract.get_shop_by_id(accounts(1)).name, "shop");
+  }
+}
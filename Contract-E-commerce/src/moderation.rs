@@ -0,0 +1,274 @@
+use crate::*;
+
+pub const MAX_REPORT_LENGTH: usize = 500;
+
+pub type ReportId = u64;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ReportTarget {
+  Product(ProductId),
+  Shop(AccountId),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ReportStatus {
+  Open,
+  Resolved,  // a moderator acted on it
+  Dismissed, // nothing wrong was found
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Report {
+  pub report_id: ReportId,
+  pub reporter: AccountId,
+  pub target: ReportTarget,
+  pub reason: String,
+  pub status: ReportStatus,
+  pub moderator: Option<AccountId>, // who closed the report
+  pub created_at: Timestamp,
+}
+
+#[near_bindgen]
+impl Contract {
+  // The report is charged to the storage deposit of the reporter
+  pub fn report_product(&mut self, product_id: ProductId, reason: String) -> Report {
+    self.assert_not_paused();
+    self.internal_get_product(&product_id);
+    self.internal_add_report(ReportTarget::Product(product_id), reason)
+  }
+
+  pub fn report_shop(&mut self, owner: AccountId, reason: String) -> Report {
+    self.assert_not_paused();
+    self.internal_get_shop(&owner);
+    self.internal_add_report(ReportTarget::Shop(owner), reason)
+  }
+
+  // Moderator: the open reports, oldest first
+  pub fn get_open_reports(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Report> {
+    let (from_index, limit) = pagination(from_index, limit);
+    self
+      .open_reports
+      .iter()
+      .skip(from_index)
+      .take(limit)
+      .filter_map(|(report_id, _)| self.reports.get(&report_id))
+      .collect()
+  }
+
+  pub fn get_report(&self, report_id: ReportId) -> Report {
+    self.reports.get(&report_id).expect("Report not found")
+  }
+
+  // Moderator: close a report once it has been acted on (`Resolved`) or found groundless (`Dismissed`)
+  pub fn close_report(&mut self, report_id: ReportId, status: ReportStatus) -> Report {
+    self.assert_role(Role::Moderator);
+    assert_ne!(status, ReportStatus::Open, "A report can only be closed as resolved or dismissed");
+    let mut report = self.get_report(report_id);
+    assert_eq!(report.status, ReportStatus::Open, "Report is already closed");
+
+    // The reporter gets back the storage of the report, the closed report is kept at the expense of the contract
+    let initial_storage_usage = env::storage_usage();
+    self.open_reports.remove(&report_id);
+    self.reports.remove(&report_id);
+    self.internal_update_storage(&report.reporter, initial_storage_usage);
+
+    report.status = status;
+    report.moderator = Some(env::predecessor_account_id());
+    self.reports.insert(&report_id, &report);
+    Event::ReportClose(vec![ReportStatusData { report_id, status }]).emit();
+    report
+  }
+
+  // Moderator: hide the product from every listing and stop its sales
+  pub fn delist_product(&mut self, product_id: ProductId) -> Product {
+    self.assert_role(Role::Moderator);
+    let mut product = self.internal_get_product(&product_id);
+    assert!(!product.delisted, "Product is already delisted");
+
+    self.internal_set_delisted(&mut product, true);
+    product
+  }
+
+  pub fn relist_product(&mut self, product_id: ProductId) -> Product {
+    self.assert_role(Role::Moderator);
+    let mut product = self.internal_get_product(&product_id);
+    assert!(product.delisted, "Product is not delisted");
+
+    self.internal_set_delisted(&mut product, false);
+    product
+  }
+
+  // Moderator: the account can no longer open a shop or list products, the products of its shop are delisted
+  pub fn ban_account(&mut self, account_id: AccountId) {
+    self.assert_role(Role::Moderator);
+    assert!(self.internal_role(&account_id).is_none(), "Accounts with a role can not be banned");
+    assert!(self.banned_accounts.insert(&account_id), "Account is already banned");
+
+    for product_id in self.internal_shop_product_ids(&account_id) {
+      let mut product = self.internal_get_product(&product_id);
+      if !product.delisted {
+        self.internal_set_delisted(&mut product, true);
+      }
+    }
+    Event::AccountBan(vec![AccountData { account_id }]).emit();
+  }
+
+  // Products delisted by the ban stay delisted until a moderator relists them
+  pub fn unban_account(&mut self, account_id: AccountId) {
+    self.assert_role(Role::Moderator);
+    assert!(self.banned_accounts.remove(&account_id), "Account is not banned");
+    Event::AccountUnban(vec![AccountData { account_id }]).emit();
+  }
+
+  pub fn is_banned(&self, account_id: AccountId) -> bool {
+    self.banned_accounts.contains(&account_id)
+  }
+}
+
+impl Contract {
+  fn internal_add_report(&mut self, target: ReportTarget, reason: String) -> Report {
+    assert!(!reason.is_empty(), "Give a reason for the report");
+    assert!(reason.len() <= MAX_REPORT_LENGTH, "Reason is longer than {} bytes", MAX_REPORT_LENGTH);
+    let initial_storage_usage = env::storage_usage();
+
+    self.total_reports += 1;
+    let report = Report {
+      report_id: self.total_reports,
+      reporter: env::signer_account_id(),
+      target,
+      reason,
+      status: ReportStatus::Open,
+      moderator: None,
+      created_at: env::block_timestamp(),
+    };
+    self.reports.insert(&report.report_id, &report);
+    self.open_reports.insert(&report.report_id, &());
+    self.internal_update_storage(&report.reporter, initial_storage_usage);
+    Event::ReportCreate(vec![ReportData {
+      report_id: report.report_id,
+      reporter: report.reporter.clone(),
+      target: report.target.clone(),
+    }])
+    .emit();
+    report
+  }
+
+  fn internal_set_delisted(&mut self, product: &mut Product, delisted: bool) {
+    product.delisted = delisted;
+    self.internal_update_product(product);
+    if delisted {
+      Event::ProductDelist(vec![(&*product).into()]).emit();
+    } else {
+      Event::ProductList(vec![(&*product).into()]).emit();
+    }
+  }
+
+  pub(crate) fn assert_not_banned(&self, account_id: &AccountId) {
+    assert!(!self.banned_accounts.contains(account_id), "Account {} is banned", account_id);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  fn setup_moderator() -> Contract {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.grant_role(accounts(3), Role::Moderator);
+    register(&mut contract, accounts(2));
+    contract
+  }
+
+  #[test]
+  fn open_reports_oldest_first() {
+    let mut contract = setup_moderator();
+    set_context(accounts(2), 0);
+    for reason in ["a", "b", "c", "d"] {
+      contract.report_product("p1".to_string(), reason.to_string());
+    }
+    let used = contract.storage_accounts.get(&accounts(2)).unwrap().used;
+    assert!(used > 0);
+
+    set_context(accounts(3), 0);
+    contract.close_report(2, ReportStatus::Dismissed);
+    let report_ids: Vec<ReportId> =
+      contract.get_open_reports(None, None).iter().map(|report| report.report_id).collect();
+    assert_eq!(report_ids, vec![1, 3, 4]);
+    assert_eq!(contract.get_open_reports(Some(1), Some(1))[0].report_id, 3);
+    assert!(contract.storage_accounts.get(&accounts(2)).unwrap().used < used);
+  }
+
+  #[test]
+  fn closed_reports_are_given_back() {
+    let mut contract = setup_moderator();
+    set_context(accounts(2), 0);
+    contract.report_product("p1".to_string(), "fake".to_string());
+    contract.report_shop(accounts(1), "scam".to_string());
+
+    set_context(accounts(3), 0);
+    contract.close_report(1, ReportStatus::Resolved);
+    contract.close_report(2, ReportStatus::Dismissed);
+    assert_eq!(contract.storage_accounts.get(&accounts(2)).unwrap().used, 0);
+    assert_eq!(contract.get_report(1).status, ReportStatus::Resolved);
+    assert_eq!(contract.get_report(2).moderator, Some(accounts(3)));
+  }
+
+  #[test]
+  #[should_panic(expected = "Register your account with storage_deposit first")]
+  fn report_needs_a_storage_deposit() {
+    let mut contract = setup_contract();
+    set_context(accounts(4), 0);
+    contract.report_shop(accounts(1), "scam".to_string());
+  }
+
+  #[test]
+  fn delisted_products_are_not_counted() {
+    let mut contract = setup_moderator();
+    set_context(accounts(0), 0);
+    contract.new_category("shirts".to_string(), "Shirts".to_string(), None);
+    set_context(accounts(1), 0);
+    contract.new_product(
+      "p2".to_string(),
+      "Ao".to_string(),
+      5,
+      50,
+      "desc".to_string(),
+      None,
+      Some("shirts".to_string()),
+      Some(vec!["sale".to_string()]),
+    );
+    let totals = |contract: &Contract| {
+      (
+        contract.get_total_products(),
+        contract.get_total_products_by_owner(accounts(1)),
+        contract.get_total_products_by_category("shirts".to_string()),
+        contract.get_total_products_by_tag("sale".to_string()),
+      )
+    };
+    assert_eq!(totals(&contract), (2, 2, 1, 1));
+
+    set_context(accounts(3), 0);
+    contract.delist_product("p2".to_string());
+    assert_eq!(totals(&contract), (1, 1, 0, 0));
+    assert!(contract.get_products_by_category("shirts".to_string(), None, None).is_empty());
+    let product_ids: Vec<ProductId> =
+      contract.get_all_products(None, None).into_iter().map(|product| product.product_id).collect();
+    assert_eq!(product_ids, vec!["p1".to_string()]);
+
+    // The tags of a delisted product can still change
+    set_context(accounts(1), 0);
+    contract.update_product("p2".to_string(), None, None, None, None, None, Some(vec!["new".to_string()]));
+    assert_eq!(contract.get_total_products_by_tag("new".to_string()), 0);
+    set_context(accounts(3), 0);
+    contract.relist_product("p2".to_string());
+    assert_eq!(totals(&contract), (2, 2, 1, 0));
+    assert_eq!(contract.get_total_products_by_tag("new".to_string()), 1);
+  }
+}
@@ -0,0 +1,306 @@
+use crate::*;
+
+pub type OrderId = u128;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum OrderStatus {
+  Paid, // orders are created paid, the payment is held in escrow
+  Shipped,
+  Delivered,
+  Refunded,
+  Resolved, // settled by an arbitrator, see `Order::refunded`
+  Returned, // the buyer sent the items back after delivery
+}
+
+impl OrderStatus {
+  pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+    use OrderStatus::*;
+    matches!(
+      (self, next),
+      (Paid, Shipped)
+        | (Paid, Refunded)
+        | (Shipped, Delivered)
+        | (Shipped, Refunded)
+        | (Paid, Resolved)
+        | (Shipped, Resolved)
+        | (Delivered, Returned)
+    )
+  }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Order {
+  pub order_id: OrderId,
+  pub buyer: AccountId,
+  pub seller: AccountId,
+  pub product_id: ProductId,
+  pub sku: Option<String>, // variant of the product
+  pub quantity: u64,
+  pub price: Balance, // price per unit charged at purchase time before coupons, averaged if only some were on sale
+  pub amount: Balance,
+  pub fee: Balance,      // platform fee taken from the amount when it is paid out to the seller
+  pub refunded: Balance, // paid back to the buyer
+  pub ft_token: Option<AccountId>, // paid in this token instead of NEAR
+  pub status: OrderStatus,
+  pub escrow_status: EscrowStatus,
+  pub release_at: Timestamp, // set when shipped, the seller can be paid from this time if the buyer did not confirm or claim
+  pub delivered_at: Timestamp, // 0 until delivered, starts the return window
+  pub return_window: u64,    // return window of the shop when the order was placed
+  pub claim: Option<String>,
+  pub reviewed: bool,
+  pub created_at: Timestamp,
+  pub updated_at: Timestamp,
+}
+
+#[near_bindgen]
+impl Contract {
+  // Seller: Paid -> Shipped, starts the escrow timeout
+  pub fn ship_order(&mut self, order_id: OrderId) -> Order {
+    self.assert_not_paused();
+    let mut order = self.get_order(order_id);
+    assert_eq!(order.seller, env::signer_account_id(), "Only the seller can ship this order");
+    order.release_at = env::block_timestamp() + self.escrow_timeout;
+    self.internal_set_order_status(&mut order, OrderStatus::Shipped);
+    order
+  }
+
+  // Buyer: Shipped -> Delivered, releases the payment to the seller
+  pub fn confirm_delivery(&mut self, order_id: OrderId) -> Order {
+    self.assert_not_paused();
+    let mut order = self.get_order(order_id);
+    assert_eq!(order.buyer, env::signer_account_id(), "Only the buyer can confirm delivery");
+    self.assert_not_disputed(order_id);
+    self.internal_set_order_status(&mut order, OrderStatus::Delivered);
+    self.internal_release_escrow(&mut order);
+    order
+  }
+
+  // Buyer or seller can cancel an order that has not been shipped, the payment is refunded from escrow.
+  // The seller can also refund a shipped order once the buyer opened a claim.
+  pub fn cancel_order(&mut self, order_id: OrderId) -> Order {
+    self.assert_not_paused();
+    let mut order = self.get_order(order_id);
+    let caller = env::signer_account_id();
+    assert!(caller == order.buyer || caller == order.seller, "Unauthorized");
+    self.assert_not_disputed(order_id);
+
+    match order.status {
+      OrderStatus::Paid => {
+        self.internal_set_order_status(&mut order, OrderStatus::Refunded);
+        self.internal_refund_escrow(&mut order);
+      }
+      OrderStatus::Shipped => {
+        assert_eq!(caller, order.seller, "Only the seller can refund a shipped order");
+        assert_eq!(order.escrow_status, EscrowStatus::Frozen, "There is no open claim");
+        self.internal_set_order_status(&mut order, OrderStatus::Refunded);
+        self.internal_refund_escrow(&mut order);
+      }
+      _ => env::panic_str("Order can not be cancelled"),
+    }
+
+    // Put the items back in stock, unless the product was deleted and its id taken by another shop
+    if let Some(mut product) = self.internal_order_product(&order) {
+      product.restock(order.sku.as_ref(), order.quantity);
+      self.internal_update_product(&product);
+    }
+
+    order
+  }
+
+  pub fn get_order(&self, order_id: OrderId) -> Order {
+    self.orders.get(&order_id).expect("Order not found")
+  }
+
+  pub fn get_orders_by_buyer(&self, buyer: AccountId) -> Vec<Order> {
+    let order_ids = self.orders_per_buyer.get(&buyer).unwrap_or_default();
+    order_ids.iter().filter_map(|order_id| self.orders.get(order_id)).collect()
+  }
+
+  pub fn get_orders_by_shop(&self, owner: AccountId) -> Vec<Order> {
+    let order_ids = self.orders_per_shop.get(&owner).unwrap_or_default();
+    order_ids.iter().filter_map(|order_id| self.orders.get(order_id)).collect()
+  }
+}
+
+impl Contract {
+  pub(crate) fn internal_create_order(
+    &mut self,
+    buyer: &AccountId,
+    product: &Product,
+    sku: Option<String>,
+    quantity: u64,
+    price: Balance,
+    amount: Balance,
+  ) -> Order {
+    self.total_orders += 1;
+    let now = env::block_timestamp();
+    let order = Order {
+      order_id: self.total_orders,
+      buyer: buyer.clone(),
+      seller: product.owner.clone(),
+      product_id: product.product_id.clone(),
+      sku,
+      quantity,
+      price,
+      amount,
+      fee: self.internal_platform_fee(amount),
+      refunded: 0,
+      ft_token: product.ft_token.clone(),
+      status: OrderStatus::Paid,
+      escrow_status: EscrowStatus::Unpaid,
+      release_at: 0,
+      delivered_at: 0,
+      return_window: self.internal_get_shop(&product.owner).return_window,
+      claim: None,
+      reviewed: false,
+      created_at: now,
+      updated_at: now,
+    };
+    self.internal_record_order_status(&order, OrderStatus::Paid);
+
+    self.orders.insert(&order.order_id, &order);
+
+    let mut buyer_orders = self.orders_per_buyer.get(buyer).unwrap_or_default();
+    buyer_orders.push(order.order_id);
+    self.orders_per_buyer.insert(buyer, &buyer_orders);
+
+    let mut shop_orders = self.orders_per_shop.get(&order.seller).unwrap_or_default();
+    shop_orders.push(order.order_id);
+    self.orders_per_shop.insert(&order.seller, &shop_orders);
+
+    order
+  }
+
+  // The product the order was placed for, if it is still sold by the seller of the order
+  pub(crate) fn internal_order_product(&self, order: &Order) -> Option<Product> {
+    self.product_by_id.get(&order.product_id).filter(|product| product.owner == order.seller)
+  }
+
+  pub(crate) fn internal_set_order_status(&mut self, order: &mut Order, status: OrderStatus) {
+    assert!(
+      order.status.can_transition_to(status),
+      "Invalid order status transition: {:?} -> {:?}",
+      order.status,
+      status
+    );
+    self.internal_record_order_status(order, status);
+    order.status = status;
+    order.updated_at = env::block_timestamp();
+    if status == OrderStatus::Delivered {
+      order.delivered_at = order.updated_at;
+    }
+    self.orders.insert(&order.order_id, order);
+    Event::OrderStatus(vec![OrderStatusData { order_id: U128(order.order_id), status }]).emit();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  #[test]
+  fn order_is_shipped_then_delivered() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 2);
+    assert_eq!((order.status, order.escrow_status), (OrderStatus::Paid, EscrowStatus::Held));
+
+    set_context_at(accounts(1), 0, 10);
+    let order = contract.ship_order(order.order_id);
+    assert_eq!((order.status, order.release_at), (OrderStatus::Shipped, 10 + DEFAULT_ESCROW_TIMEOUT));
+
+    set_context_at(accounts(2), 0, 20);
+    let order = contract.confirm_delivery(order.order_id);
+    assert_eq!((order.status, order.delivered_at), (OrderStatus::Delivered, 20));
+    assert_eq!(transfers(), vec![(accounts(1), 200)]);
+  }
+
+  #[test]
+  fn cancelled_order_is_refunded_and_restocked() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 3);
+    assert_eq!(contract.get_product_by_id("p1".to_string()).total_supply, 7);
+
+    set_context(accounts(2), 0);
+    let order = contract.cancel_order(order.order_id);
+    assert_eq!((order.status, order.escrow_status), (OrderStatus::Refunded, EscrowStatus::Refunded));
+    assert_eq!(transfers(), vec![(accounts(2), 300)]);
+    assert_eq!(contract.get_product_by_id("p1".to_string()).total_supply, 10);
+  }
+
+  #[test]
+  #[should_panic(expected = "Only the seller can ship this order")]
+  fn only_seller_ships() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    contract.ship_order(order.order_id);
+  }
+
+  #[test]
+  #[should_panic(expected = "Only the buyer can confirm delivery")]
+  fn only_buyer_confirms() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+    contract.confirm_delivery(order.order_id);
+  }
+
+  #[test]
+  #[should_panic(expected = "Invalid order status transition: Paid -> Delivered")]
+  fn unshipped_order_can_not_be_confirmed() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    contract.confirm_delivery(order.order_id);
+  }
+
+  #[test]
+  #[should_panic(expected = "Unauthorized")]
+  fn only_buyer_or_seller_cancels() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context(accounts(3), 0);
+    contract.cancel_order(order.order_id);
+  }
+
+  #[test]
+  #[should_panic(expected = "Only the seller can refund a shipped order")]
+  fn buyer_can_not_cancel_a_shipped_order() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+    set_context(accounts(2), 0);
+    contract.cancel_order(order.order_id);
+  }
+
+  #[test]
+  #[should_panic(expected = "There is no open claim")]
+  fn shipped_order_is_refunded_only_after_a_claim() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+    contract.cancel_order(order.order_id);
+  }
+
+  #[test]
+  fn cancel_does_not_restock_a_reused_product_id() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 3);
+    set_context(accounts(1), 0);
+    contract.delete_product("p1".to_string());
+    register(&mut contract, accounts(3));
+    set_context(accounts(3), 0);
+    contract.new_shop("Shop 2".to_string(), "desc".to_string());
+    contract.new_product("p1".to_string(), "Ao".to_string(), 5, 50, "desc".to_string(), None, None, None);
+
+    set_context(accounts(2), 0);
+    contract.cancel_order(order.order_id);
+    assert_eq!(contract.get_product_by_id("p1".to_string()).total_supply, 5);
+  }
+}
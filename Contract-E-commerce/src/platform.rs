@@ -0,0 +1,143 @@
+use crate::*;
+
+// Fees are expressed in basis points, 10_000 = 100%
+pub const FEE_DIVISOR: u32 = 10_000;
+
+#[near_bindgen]
+impl Contract {
+  pub fn set_platform_fee(&mut self, fee: u32) {
+    self.assert_role(Role::Owner);
+    assert!(fee <= FEE_DIVISOR, "Fee can not be greater than {}", FEE_DIVISOR);
+    self.platform_fee = fee;
+  }
+
+  // Withdraw the accrued fees, everything if no amount is given
+  pub fn withdraw_platform_balance(&mut self, amount: Option<Balance>) -> Promise {
+    self.assert_role(Role::Owner);
+    let amount = amount.unwrap_or(self.platform_balance);
+    assert!(amount > 0, "Nothing to withdraw");
+    assert!(amount <= self.platform_balance, "Not enough platform balance");
+
+    self.platform_balance -= amount;
+    Promise::new(self.platform_name.clone()).transfer(amount)
+  }
+
+  pub fn withdraw_platform_ft_balance(&mut self, token_id: AccountId, amount: Option<Balance>) {
+    self.assert_role(Role::Owner);
+    let balance = self.get_platform_ft_balance(token_id.clone());
+    let amount = amount.unwrap_or(balance);
+    assert!(amount > 0, "Nothing to withdraw");
+    assert!(amount <= balance, "Not enough platform balance");
+
+    self.platform_ft_balances.insert(&token_id, &(balance - amount));
+    self.internal_transfer(self.platform_name.clone(), Some(token_id), amount);
+  }
+
+  pub fn get_platform_fee(&self) -> u32 {
+    self.platform_fee
+  }
+
+  // Fees in NEAR that can still be withdrawn
+  pub fn get_platform_balance(&self) -> Balance {
+    self.platform_balance
+  }
+
+  // All fees ever collected in NEAR
+  pub fn get_total_platform_fees(&self) -> Balance {
+    self.total_platform_fees
+  }
+
+  // Fees collected in a fungible token that can still be withdrawn
+  pub fn get_platform_ft_balance(&self, token_id: AccountId) -> Balance {
+    self.platform_ft_balances.get(&token_id).unwrap_or(0)
+  }
+}
+
+impl Contract {
+  pub(crate) fn internal_platform_fee(&self, amount: Balance) -> Balance {
+    amount * self.platform_fee as u128 / FEE_DIVISOR as u128
+  }
+
+  pub(crate) fn internal_collect_fee(&mut self, ft_token: Option<&AccountId>, fee: Balance) {
+    match ft_token {
+      Some(token_id) => {
+        let balance = self.get_platform_ft_balance(token_id.clone());
+        self.platform_ft_balances.insert(token_id, &(balance + fee));
+      }
+      None => {
+        self.platform_balance += fee;
+        self.total_platform_fees += fee;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  fn setup_with_fee(fee: u32) -> Contract {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.set_platform_fee(fee);
+    contract
+  }
+
+  #[test]
+  fn fee_is_taken_from_the_payout() {
+    let mut contract = setup_with_fee(250);
+    let order = buy(&mut contract, accounts(2), 4);
+    assert_eq!(order.fee, 10);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+
+    set_context(accounts(2), 0);
+    contract.confirm_delivery(order.order_id);
+    assert_eq!(transfers(), vec![(accounts(1), 390)]);
+    assert_eq!(contract.get_platform_balance(), 10);
+    assert_eq!(contract.get_total_platform_fees(), 10);
+
+    set_context(accounts(0), 0);
+    contract.withdraw_platform_balance(None);
+    assert_eq!(transfers(), vec![(accounts(0), 10)]);
+    assert_eq!(contract.get_platform_balance(), 0);
+    assert_eq!(contract.get_total_platform_fees(), 10);
+  }
+
+  #[test]
+  fn fee_is_locked_at_purchase() {
+    let mut contract = setup_with_fee(1_000);
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context(accounts(0), 0);
+    contract.set_platform_fee(5_000);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+
+    set_context(accounts(2), 0);
+    contract.confirm_delivery(order.order_id);
+    assert_eq!(transfers(), vec![(accounts(1), 90)]);
+    assert_eq!(contract.get_platform_balance(), 10);
+  }
+
+  #[test]
+  fn refund_takes_no_fee() {
+    let mut contract = setup_with_fee(250);
+    let order = buy(&mut contract, accounts(2), 4);
+
+    set_context(accounts(1), 0);
+    contract.cancel_order(order.order_id);
+    assert_eq!(transfers(), vec![(accounts(2), 400)]);
+    assert_eq!(contract.get_platform_balance(), 0);
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires the Owner role")]
+  fn only_owner_withdraws() {
+    let mut contract = setup_with_fee(250);
+    set_context(accounts(1), 0);
+    contract.withdraw_platform_balance(None);
+  }
+}
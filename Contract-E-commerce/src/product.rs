@@ -0,0 +1,148 @@
+use crate::*;
+
+pub type ProductId = String;
+
+pub const MAX_PRODUCT_ID_LENGTH: usize = 64;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Product {
+  pub product_id: ProductId,
+  pub name: String,
+  pub total_supply: u64,
+  pub price: Balance,
+  pub desc: String, // description
+  pub owner: AccountId,
+  pub ft_token: Option<AccountId>, // the price is in this token instead of NEAR
+  pub category: Option<CategoryId>,
+  pub tags: Vec<String>,
+  pub rating_sum: u64, // average rating = rating_sum / rating_count
+  pub rating_count: u64,
+  pub sale: Option<Sale>,
+  pub variants: Vec<Variant>,
+  pub low_stock_threshold: Option<u64>,
+  pub delisted: bool, // hidden from listings and not for sale, set by moderators
+}
+
+// Every product is stored in `product_by_id`, `products` and `products_per_shop`,
+// and indexed by category, tags and name words.
+// These methods are the only place that write to them so the indexes never go out of sync.
+impl Contract {
+  pub(crate) fn internal_get_product(&self, product_id: &ProductId) -> Product {
+    self.product_by_id.get(product_id).expect("Product not found")
+  }
+
+  pub(crate) fn internal_add_product(&mut self, product: &Product) {
+    assert!(!self.product_by_id.contains_key(&product.product_id), "Product already exists");
+
+    self.total_products += 1;
+    self.internal_store_product(product, self.total_products);
+    Event::ProductList(vec![product.into()]).emit();
+  }
+
+  pub(crate) fn internal_update_product(&mut self, product: &Product) {
+    let index = self.product_index.get(&product.product_id).expect("Product not found");
+    let old_product = self.internal_get_product(&product.product_id);
+    self.internal_check_low_stock(&old_product, product);
+    if old_product.category != product.category
+      || old_product.tags != product.tags
+      || old_product.delisted != product.delisted
+    {
+      self.internal_unindex_product_category(&old_product);
+      self.internal_index_product_category(product);
+    }
+    if old_product.name != product.name {
+      self.internal_unindex_product_name(&old_product);
+      self.internal_index_product_name(product);
+    }
+    if old_product.delisted != product.delisted {
+      self.internal_count_delisted(&product.owner, product.delisted);
+    }
+    self.products.insert(&index, product);
+    self.product_by_id.insert(&product.product_id, product);
+    Event::ProductUpdate(vec![product.into()]).emit();
+  }
+
+  fn internal_count_shop_products(&mut self, owner: &AccountId) {
+    if let Some(mut shop) = self.internal_shop(owner) {
+      shop.total_product = self.products_per_shop.get(owner).map(|product_ids| product_ids.len()).unwrap_or(0);
+      self.internal_update_shop(&shop);
+    }
+  }
+
+  pub(crate) fn internal_remove_product(&mut self, product_id: &ProductId) -> Product {
+    let (product, _) = self.internal_unstore_product(product_id);
+    self.internal_save_reservations(product_id, Vec::new());
+    Event::ProductDelist(vec![(&product).into()]).emit();
+
+    product
+  }
+
+  // Write the product and every index of it, `index` is its key in `products`
+  pub(crate) fn internal_store_product(&mut self, product: &Product, index: u128) {
+    self.products.insert(&index, product);
+    self.product_index.insert(&product.product_id, &index);
+    self.product_by_id.insert(&product.product_id, product);
+
+    let mut product_ids = self.products_per_shop.get(&product.owner).unwrap_or_else(|| {
+      UnorderedSet::new(
+        StorageKey::ProductsPerShopInnerKey { account_hash: env::sha256_array(product.owner.as_bytes()) }
+          .try_to_vec()
+          .unwrap(),
+      )
+    });
+    product_ids.insert(&product.product_id);
+    self.products_per_shop.insert(&product.owner, &product_ids);
+    self.internal_count_shop_products(&product.owner);
+    if product.delisted {
+      self.internal_count_delisted(&product.owner, true);
+    }
+    self.internal_index_product_category(product);
+    self.internal_index_product_name(product);
+  }
+
+  // Remove the product and every index of it, returns it with its key in `products`.
+  // Reservations are kept, e.g. when the product only changes owner. Wishlist entries are always kept,
+  // they are paid by their account until it removes them.
+  pub(crate) fn internal_unstore_product(&mut self, product_id: &ProductId) -> (Product, u128) {
+    let product = self.product_by_id.remove(product_id).expect("Product not found");
+    let index = self.product_index.remove(product_id).expect("Product not found");
+    self.products.remove(&index);
+    self.internal_unindex_product_category(&product);
+    self.internal_unindex_product_name(&product);
+
+    if let Some(mut product_ids) = self.products_per_shop.get(&product.owner) {
+      product_ids.remove(product_id);
+      if product_ids.is_empty() {
+        self.products_per_shop.remove(&product.owner);
+      } else {
+        self.products_per_shop.insert(&product.owner, &product_ids);
+      }
+    }
+    self.internal_count_shop_products(&product.owner);
+    if product.delisted {
+      self.internal_count_delisted(&product.owner, false);
+    }
+    (product, index)
+  }
+
+  // Delisted products are counted in total and per shop to leave them out of the product counts
+  fn internal_count_delisted(&mut self, owner: &AccountId, delisted: bool) {
+    let count = self.delisted_per_shop.get(owner).unwrap_or(0);
+    if delisted {
+      self.total_delisted += 1;
+      self.delisted_per_shop.insert(owner, &(count + 1));
+    } else {
+      self.total_delisted -= 1;
+      if count > 1 {
+        self.delisted_per_shop.insert(owner, &(count - 1));
+      } else {
+        self.delisted_per_shop.remove(owner);
+      }
+    }
+  }
+
+  pub(crate) fn internal_shop_product_ids(&self, owner: &AccountId) -> Vec<ProductId> {
+    self.products_per_shop.get(owner).map(|product_ids| product_ids.to_vec()).unwrap_or_default()
+  }
+}
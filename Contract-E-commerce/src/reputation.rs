@@ -0,0 +1,96 @@
+use crate::*;
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ShopStats {
+  pub owner: AccountId,
+  pub total_product: u64,
+  pub total_orders: u64,
+  pub completed_orders: u64,
+  pub cancelled_orders: u64,
+  pub cancellation_rate: u32, // basis points of the orders that were cancelled
+  pub rating_count: u64,
+  pub average_rating: u32, // hundredths of a star, 450 = 4.5 stars
+  pub dispute_losses: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+  pub fn get_shop_stats(&self, owner: AccountId) -> ShopStats {
+    let shop = self.internal_get_shop(&owner);
+    let cancellation_rate = match shop.total_orders {
+      0 => 0,
+      total => (shop.cancelled_orders * FEE_DIVISOR as u64 / total) as u32,
+    };
+    let average_rating = match shop.rating_count {
+      0 => 0,
+      count => (shop.rating_sum * 100 / count) as u32,
+    };
+
+    ShopStats {
+      owner,
+      total_product: shop.total_product,
+      total_orders: shop.total_orders,
+      completed_orders: shop.completed_orders,
+      cancelled_orders: shop.cancelled_orders,
+      cancellation_rate,
+      rating_count: shop.rating_count,
+      average_rating,
+      dispute_losses: shop.dispute_losses,
+    }
+  }
+}
+
+impl Contract {
+  // Keep the reputation of the seller up to date when an order moves to `status`
+  pub(crate) fn internal_record_order_status(&mut self, order: &Order, status: OrderStatus) {
+    let Some(mut shop) = self.internal_shop(&order.seller) else { return };
+
+    match (order.status, status) {
+      (_, OrderStatus::Paid) => shop.total_orders += 1,
+      (_, OrderStatus::Delivered) => shop.completed_orders += 1,
+      // Only the cancellations of the seller count, a buyer cancelling is not the fault of the shop
+      (OrderStatus::Paid, OrderStatus::Refunded) if env::signer_account_id() == order.seller => {
+        shop.cancelled_orders += 1
+      }
+      // The seller refunded a shipped order after the buyer opened a claim
+      (OrderStatus::Shipped, OrderStatus::Refunded) => shop.dispute_losses += 1,
+      // An arbitrator gave part of the payment back to the buyer, or sided with the seller
+      (_, OrderStatus::Resolved) if order.refunded > 0 => shop.dispute_losses += 1,
+      (_, OrderStatus::Resolved) => shop.completed_orders += 1,
+      _ => return,
+    }
+
+    self.internal_update_shop(&shop);
+  }
+
+  pub(crate) fn internal_record_rating(&mut self, seller: &AccountId, rating: u8) {
+    if let Some(mut shop) = self.internal_shop(seller) {
+      shop.rating_sum += rating as u64;
+      shop.rating_count += 1;
+      self.internal_update_shop(&shop);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use crate::test_utils::*;
+
+  #[test]
+  fn only_seller_cancellations_count() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context(accounts(2), 0);
+    contract.cancel_order(order.order_id);
+    assert_eq!(contract.get_shop_stats(accounts(1)).cancelled_orders, 0);
+
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context(accounts(1), 0);
+    contract.cancel_order(order.order_id);
+    let stats = contract.get_shop_stats(accounts(1));
+    assert_eq!((stats.total_orders, stats.cancelled_orders, stats.cancellation_rate), (2, 1, 5_000));
+  }
+}
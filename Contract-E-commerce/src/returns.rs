@@ -0,0 +1,233 @@
+use crate::*;
+
+// 7 days
+pub const DEFAULT_RETURN_WINDOW: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ReturnStatus {
+  Requested,
+  Approved, // the items are back in stock and `refund_amount` was paid back to the buyer
+  Rejected,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReturnRequest {
+  pub order_id: OrderId,
+  pub quantity: u64, // items sent back, can be less than the order quantity
+  pub reason: String,
+  pub status: ReturnStatus,
+  pub refund_amount: Balance,
+  pub seller_note: Option<String>,
+  pub requested_at: Timestamp,
+  pub closed_at: Option<Timestamp>,
+}
+
+#[near_bindgen]
+impl Contract {
+  // Shop owner: how long after delivery buyers can ask for a return, 0 to stop accepting returns.
+  // Orders keep the window the shop had when they were placed.
+  pub fn set_return_window(&mut self, return_window: u64) -> Shop {
+    self.assert_not_paused();
+    let mut shop = self.internal_get_shop(&env::signer_account_id());
+    shop.return_window = return_window;
+    self.internal_update_shop(&shop);
+    shop
+  }
+
+  // Buyer: one return request per delivered order, within the return window of the order
+  pub fn request_return(&mut self, order_id: OrderId, quantity: u64, reason: String) -> ReturnRequest {
+    self.assert_not_paused();
+    let order = self.get_order(order_id);
+    assert_eq!(order.buyer, env::signer_account_id(), "Only the buyer can return this order");
+    assert_eq!(order.status, OrderStatus::Delivered, "Only a delivered order can be returned");
+    assert!(self.returns.get(&order_id).is_none(), "A return was already requested for this order");
+    assert!(quantity > 0 && quantity <= order.quantity, "Quantity must be between 1 and {}", order.quantity);
+    assert!(reason.len() <= MAX_EVIDENCE_LENGTH, "Reason is longer than {} bytes", MAX_EVIDENCE_LENGTH);
+    assert!(env::block_timestamp() <= order.delivered_at.saturating_add(order.return_window), "Return window is over");
+
+    let return_request = ReturnRequest {
+      order_id,
+      quantity,
+      reason,
+      status: ReturnStatus::Requested,
+      refund_amount: 0,
+      seller_note: None,
+      requested_at: env::block_timestamp(),
+      closed_at: None,
+    };
+    self.returns.insert(&order_id, &return_request);
+    Event::ReturnRequest(vec![ReturnData { order_id: U128(order_id), quantity, status: return_request.status }]).emit();
+    return_request
+  }
+
+  // Seller: accept the return and refund `refund_amount`, at most what the buyer paid.
+  // The payment has already been released to the seller, so the refund is attached to this call.
+  // Returns of orders paid in a fungible token are approved with `ft_transfer_call`, see `FtReturnMsg`.
+  #[payable]
+  pub fn approve_return(&mut self, order_id: OrderId, refund_amount: Balance) -> ReturnRequest {
+    self.assert_not_paused();
+    let order = self.get_order(order_id);
+    let seller = env::predecessor_account_id();
+    assert_eq!(order.seller, seller, "Only the seller can approve a return");
+    assert!(order.ft_token.is_none(), "Order was paid in a fungible token, refund with ft_transfer_call");
+    let deposit = env::attached_deposit();
+    assert!(deposit >= refund_amount, "Attach the refund amount");
+
+    let return_request = self.internal_approve_return(order, refund_amount);
+    if deposit > refund_amount {
+      Promise::new(seller).transfer(deposit - refund_amount);
+    }
+    return_request
+  }
+
+  pub fn reject_return(&mut self, order_id: OrderId, note: String) -> ReturnRequest {
+    self.assert_not_paused();
+    let order = self.get_order(order_id);
+    assert_eq!(order.seller, env::predecessor_account_id(), "Only the seller can reject a return");
+    let mut return_request = self.get_return(order_id);
+    assert_eq!(return_request.status, ReturnStatus::Requested, "Return is already closed");
+
+    return_request.status = ReturnStatus::Rejected;
+    return_request.seller_note = Some(note);
+    return_request.closed_at = Some(env::block_timestamp());
+    self.returns.insert(&order_id, &return_request);
+    Event::ReturnReject(vec![ReturnData {
+      order_id: U128(order_id),
+      quantity: return_request.quantity,
+      status: return_request.status,
+    }])
+    .emit();
+    return_request
+  }
+
+  pub fn get_return(&self, order_id: OrderId) -> ReturnRequest {
+    self.returns.get(&order_id).expect("Return not found")
+  }
+}
+
+impl Contract {
+  // Put the returned items back in stock and pay `refund_amount` to the buyer, the seller already sent it
+  pub(crate) fn internal_approve_return(&mut self, mut order: Order, refund_amount: Balance) -> ReturnRequest {
+    let mut return_request = self.get_return(order.order_id);
+    assert_eq!(return_request.status, ReturnStatus::Requested, "Return is already closed");
+    assert!(refund_amount <= order.amount - order.refunded, "Can not refund more than the order amount");
+
+    if let Some(mut product) = self.internal_order_product(&order) {
+      product.restock(order.sku.as_ref(), return_request.quantity);
+      self.internal_update_product(&product);
+    }
+
+    order.refunded += refund_amount;
+    self.internal_set_order_status(&mut order, OrderStatus::Returned);
+    if refund_amount > 0 {
+      self.internal_transfer(order.buyer.clone(), order.ft_token.clone(), refund_amount);
+      Event::Refund(vec![PaymentData {
+        order_id: U128(order.order_id),
+        receiver_id: order.buyer.clone(),
+        amount: U128(refund_amount),
+        fee: U128(0),
+        ft_token: order.ft_token.clone(),
+      }])
+      .emit();
+    }
+
+    return_request.status = ReturnStatus::Approved;
+    return_request.refund_amount = refund_amount;
+    return_request.closed_at = Some(env::block_timestamp());
+    self.returns.insert(&order.order_id, &return_request);
+    Event::ReturnApprove(vec![ReturnData {
+      order_id: U128(order.order_id),
+      quantity: return_request.quantity,
+      status: return_request.status,
+    }])
+    .emit();
+    return_request
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::{accounts, VMContextBuilder};
+  use near_sdk::testing_env;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  // Order 1 of `accounts(2)`, 3 items for 300 yoctoNEAR, delivered at `delivered_at`
+  fn setup_delivered(delivered_at: Timestamp) -> (Contract, Order) {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 3);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+    set_context_at(accounts(2), 0, delivered_at);
+    let order = contract.confirm_delivery(order.order_id);
+    (contract, order)
+  }
+
+  #[test]
+  fn partial_refund_is_paid_by_the_seller() {
+    let (mut contract, order) = setup_delivered(0);
+    set_context(accounts(2), 0);
+    contract.request_return(order.order_id, 1, "sai size".to_string());
+
+    set_context(accounts(1), 150);
+    let return_request = contract.approve_return(order.order_id, 100);
+    assert_eq!((return_request.status, return_request.refund_amount), (ReturnStatus::Approved, 100));
+    assert_eq!(transfers(), vec![(accounts(2), 100), (accounts(1), 50)]);
+    let order = contract.get_order(order.order_id);
+    assert_eq!((order.status, order.refunded), (OrderStatus::Returned, 100));
+    assert_eq!(contract.get_product_by_id("p1".to_string()).total_supply, 8);
+  }
+
+  #[test]
+  fn return_window_is_kept_from_purchase() {
+    let mut contract = setup_contract();
+    let order = buy(&mut contract, accounts(2), 1);
+    assert_eq!(order.return_window, DEFAULT_RETURN_WINDOW);
+    set_context(accounts(1), 0);
+    contract.set_return_window(0);
+    contract.ship_order(order.order_id);
+    set_context(accounts(2), 0);
+    contract.confirm_delivery(order.order_id);
+
+    set_context_at(accounts(2), 0, DEFAULT_RETURN_WINDOW);
+    contract.request_return(order.order_id, 1, "sai size".to_string());
+  }
+
+  #[test]
+  #[should_panic(expected = "Return window is over")]
+  fn return_after_the_window() {
+    let (mut contract, order) = setup_delivered(0);
+    set_context_at(accounts(2), 0, DEFAULT_RETURN_WINDOW + 1);
+    contract.request_return(order.order_id, 1, "sai size".to_string());
+  }
+
+  #[test]
+  fn return_window_does_not_overflow() {
+    let mut contract = setup_contract();
+    set_context(accounts(1), 0);
+    contract.set_return_window(u64::MAX);
+    let order = buy(&mut contract, accounts(2), 1);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+    set_context_at(accounts(2), 0, 1_000);
+    contract.confirm_delivery(order.order_id);
+
+    contract.request_return(order.order_id, 1, "sai size".to_string());
+  }
+
+  #[test]
+  #[should_panic(expected = "Only the seller can reject a return")]
+  fn contract_called_by_the_seller_can_not_reject() {
+    let (mut contract, order) = setup_delivered(0);
+    set_context(accounts(2), 0);
+    contract.request_return(order.order_id, 1, "sai size".to_string());
+
+    let mut builder = VMContextBuilder::new();
+    builder.current_account_id(accounts(0)).signer_account_id(accounts(1)).predecessor_account_id(accounts(5));
+    testing_env!(builder.build());
+    contract.reject_return(order.order_id, "used".to_string());
+  }
+}
@@ -0,0 +1,110 @@
+use crate::*;
+
+pub const MAX_REVIEW_LENGTH: usize = 1000;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Review {
+  pub order_id: OrderId,
+  pub product_id: ProductId,
+  pub reviewer: AccountId,
+  pub rating: u8, // 1 to 5 stars
+  pub text: String,
+  pub created_at: Timestamp,
+}
+
+#[near_bindgen]
+impl Contract {
+  // Buyer: one review per delivered order
+  pub fn post_review(&mut self, order_id: OrderId, rating: u8, text: String) -> Review {
+    self.assert_not_paused();
+    let mut order = self.get_order(order_id);
+    let reviewer = env::signer_account_id();
+    assert_eq!(order.buyer, reviewer, "Only the buyer of the order can review it");
+    assert_ne!(order.buyer, order.seller, "You can not review your own product");
+    assert_eq!(order.status, OrderStatus::Delivered, "Only a delivered order can be reviewed");
+    assert!(!order.reviewed, "Order already reviewed");
+    assert!((1..=5).contains(&rating), "Rating must be between 1 and 5");
+    assert!(text.len() <= MAX_REVIEW_LENGTH, "Review is longer than {} bytes", MAX_REVIEW_LENGTH);
+
+    // A deleted product whose id was taken by another shop is not the one the buyer received
+    let mut product = self.internal_order_product(&order).expect("Product not found");
+    product.rating_sum += rating as u64;
+    product.rating_count += 1;
+    self.internal_update_product(&product);
+    self.internal_record_rating(&order.seller, rating);
+
+    order.reviewed = true;
+    self.orders.insert(&order_id, &order);
+
+    let review =
+      Review { order_id, product_id: product.product_id, reviewer, rating, text, created_at: env::block_timestamp() };
+    let mut reviews = self.reviews_per_product.get(&review.product_id).unwrap_or_else(|| {
+      Vector::new(
+        StorageKey::ReviewsPerProductInnerKey { product_hash: env::sha256_array(review.product_id.as_bytes()) }
+          .try_to_vec()
+          .unwrap(),
+      )
+    });
+    reviews.push(&review);
+    self.reviews_per_product.insert(&review.product_id, &reviews);
+
+    review
+  }
+
+  pub fn get_reviews(&self, product_id: ProductId, from_index: Option<u64>, limit: Option<u64>) -> Vec<Review> {
+    let (from_index, limit) = pagination(from_index, limit);
+    match self.reviews_per_product.get(&product_id) {
+      Some(reviews) => reviews.iter().skip(from_index).take(limit).collect(),
+      None => Vec::new(),
+    }
+  }
+
+  pub fn get_total_reviews(&self, product_id: ProductId) -> u64 {
+    self.reviews_per_product.get(&product_id).map(|reviews| reviews.len()).unwrap_or(0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  fn delivered_order(contract: &mut Contract) -> Order {
+    let order = buy(contract, accounts(2), 1);
+    set_context(accounts(1), 0);
+    contract.ship_order(order.order_id);
+    set_context(accounts(2), 0);
+    contract.confirm_delivery(order.order_id)
+  }
+
+  #[test]
+  fn review_rates_the_product_and_the_shop() {
+    let mut contract = setup_contract();
+    let order = delivered_order(&mut contract);
+    contract.post_review(order.order_id, 4, "good".to_string());
+
+    let product = contract.get_product_by_id("p1".to_string());
+    assert_eq!((product.rating_sum, product.rating_count), (4, 1));
+    assert_eq!(contract.get_shop_stats(accounts(1)).rating_count, 1);
+    assert_eq!(contract.get_reviews("p1".to_string(), None, None)[0].reviewer, accounts(2));
+  }
+
+  #[test]
+  #[should_panic(expected = "Product not found")]
+  fn review_does_not_rate_a_reused_product_id() {
+    let mut contract = setup_contract();
+    let order = delivered_order(&mut contract);
+    set_context(accounts(1), 0);
+    contract.delete_product("p1".to_string());
+    register(&mut contract, accounts(3));
+    set_context(accounts(3), 0);
+    contract.new_shop("Shop 2".to_string(), "desc".to_string());
+    contract.new_product("p1".to_string(), "Ao".to_string(), 5, 50, "desc".to_string(), None, None, None);
+
+    set_context(accounts(2), 0);
+    contract.post_review(order.order_id, 1, "bad".to_string());
+  }
+}
@@ -0,0 +1,227 @@
+use crate::*;
+
+// Each role can do everything the roles of a lower rank can, except settling disputes
+// which only arbitrators do. New roles are added at the end to keep the stored ones.
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+  Moderator,  // handles reports and listings
+  Admin,      // manages categories, tokens, escrow settings and can pause the marketplace
+  Owner,      // the `platform_name` account, manages fees and admins
+  Arbitrator, // settles order disputes
+}
+
+impl Role {
+  pub fn rank(&self) -> u8 {
+    match self {
+      Role::Moderator | Role::Arbitrator => 1,
+      Role::Admin => 2,
+      Role::Owner => 3,
+    }
+  }
+}
+
+#[near_bindgen]
+impl Contract {
+  // Only a higher role can grant or revoke a role: the owner manages admins, admins manage moderators and arbitrators
+  pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+    assert_ne!(role, Role::Owner, "There is only one owner");
+    self.assert_role_above(role);
+    if let Some(current_role) = self.internal_role(&account_id) {
+      self.assert_role_above(current_role);
+    }
+
+    self.roles.insert(&account_id, &role);
+    Event::RoleGrant(vec![RoleData { account_id, role }]).emit();
+  }
+
+  pub fn revoke_role(&mut self, account_id: AccountId) {
+    let role = self.roles.get(&account_id).expect("Account has no role");
+    self.assert_role_above(role);
+
+    self.roles.remove(&account_id);
+    Event::RoleRevoke(vec![RoleData { account_id, role }]).emit();
+  }
+
+  pub fn get_role(&self, account_id: AccountId) -> Option<Role> {
+    self.internal_role(&account_id)
+  }
+
+  pub fn get_roles(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<(AccountId, Role)> {
+    let (from_index, limit) = pagination(from_index, limit);
+    self.roles.iter().skip(from_index).take(limit).collect()
+  }
+
+  // Stop every marketplace call that changes state, views keep working
+  pub fn pause(&mut self) {
+    self.assert_role(Role::Admin);
+    assert!(!self.paused, "The marketplace is already paused");
+    self.paused = true;
+    Event::Pause(vec![PauseData { account_id: env::predecessor_account_id() }]).emit();
+  }
+
+  pub fn unpause(&mut self) {
+    self.assert_role(Role::Admin);
+    assert!(self.paused, "The marketplace is not paused");
+    self.paused = false;
+    Event::Unpause(vec![PauseData { account_id: env::predecessor_account_id() }]).emit();
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+}
+
+impl Contract {
+  pub(crate) fn internal_role(&self, account_id: &AccountId) -> Option<Role> {
+    if account_id == &self.platform_name {
+      Some(Role::Owner)
+    } else {
+      self.roles.get(account_id)
+    }
+  }
+
+  // Roles are checked on the predecessor, so a contract called by a privileged account can not act for it
+  pub(crate) fn assert_role(&self, role: Role) {
+    let caller_role = self.internal_role(&env::predecessor_account_id());
+    assert!(
+      matches!(caller_role, Some(caller_role) if caller_role == role || (role != Role::Arbitrator && caller_role.rank() > role.rank())),
+      "Requires the {:?} role",
+      role
+    );
+  }
+
+  fn assert_role_above(&self, role: Role) {
+    let caller_role = self.internal_role(&env::predecessor_account_id());
+    assert!(
+      matches!(caller_role, Some(caller_role) if caller_role.rank() > role.rank()),
+      "Requires a role above {:?}",
+      role
+    );
+  }
+
+  pub(crate) fn assert_not_paused(&self) {
+    assert!(!self.paused, "The marketplace is paused");
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::{accounts, VMContextBuilder};
+  use near_sdk::testing_env;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  // `accounts(2)` is an admin and `accounts(3)` a moderator
+  fn setup_roles() -> Contract {
+    let mut contract = setup_contract();
+    set_context(accounts(0), 0);
+    contract.grant_role(accounts(2), Role::Admin);
+    set_context(accounts(2), 0);
+    contract.grant_role(accounts(3), Role::Moderator);
+    contract
+  }
+
+  #[test]
+  fn higher_roles_grant_and_revoke() {
+    let mut contract = setup_roles();
+    assert_eq!(contract.get_role(accounts(0)), Some(Role::Owner));
+    assert_eq!(contract.get_role(accounts(2)), Some(Role::Admin));
+    assert_eq!(contract.get_role(accounts(3)), Some(Role::Moderator));
+
+    set_context(accounts(2), 0);
+    contract.grant_role(accounts(3), Role::Arbitrator);
+    assert_eq!(contract.get_role(accounts(3)), Some(Role::Arbitrator));
+    contract.revoke_role(accounts(3));
+    assert_eq!(contract.get_role(accounts(3)), None);
+
+    set_context(accounts(0), 0);
+    contract.revoke_role(accounts(2));
+    assert_eq!(contract.get_role(accounts(2)), None);
+    assert!(contract.get_roles(None, None).is_empty());
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires a role above Admin")]
+  fn admin_can_not_grant_admin() {
+    let mut contract = setup_roles();
+    set_context(accounts(2), 0);
+    contract.grant_role(accounts(4), Role::Admin);
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires a role above Admin")]
+  fn admin_can_not_demote_admin() {
+    let mut contract = setup_roles();
+    set_context(accounts(0), 0);
+    contract.grant_role(accounts(4), Role::Admin);
+    set_context(accounts(2), 0);
+    contract.grant_role(accounts(4), Role::Moderator);
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires a role above Moderator")]
+  fn moderator_can_not_grant() {
+    let mut contract = setup_roles();
+    set_context(accounts(3), 0);
+    contract.grant_role(accounts(4), Role::Moderator);
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires a role above Admin")]
+  fn admin_can_not_revoke_admin() {
+    let mut contract = setup_roles();
+    set_context(accounts(0), 0);
+    contract.grant_role(accounts(4), Role::Admin);
+    set_context(accounts(2), 0);
+    contract.revoke_role(accounts(4));
+  }
+
+  #[test]
+  #[should_panic(expected = "There is only one owner")]
+  fn owner_can_not_be_granted() {
+    let mut contract = setup_roles();
+    set_context(accounts(0), 0);
+    contract.grant_role(accounts(2), Role::Owner);
+  }
+
+  #[test]
+  fn admin_pauses_and_unpauses() {
+    let mut contract = setup_roles();
+    set_context(accounts(2), 0);
+    contract.pause();
+    assert!(contract.is_paused());
+    contract.unpause();
+    assert!(!contract.is_paused());
+
+    buy(&mut contract, accounts(4), 1);
+  }
+
+  #[test]
+  #[should_panic(expected = "The marketplace is paused")]
+  fn paused_marketplace_rejects_calls() {
+    let mut contract = setup_roles();
+    set_context(accounts(2), 0);
+    contract.pause();
+    buy(&mut contract, accounts(4), 1);
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires the Admin role")]
+  fn moderator_can_not_pause() {
+    let mut contract = setup_roles();
+    set_context(accounts(3), 0);
+    contract.pause();
+  }
+
+  #[test]
+  #[should_panic(expected = "Requires the Admin role")]
+  fn contract_called_by_admin_can_not_pause() {
+    let mut contract = setup_roles();
+    let mut builder = VMContextBuilder::new();
+    builder.current_account_id(accounts(0)).signer_account_id(accounts(2)).predecessor_account_id(accounts(5));
+    testing_env!(builder.build());
+    contract.pause();
+  }
+}
@@ -0,0 +1,157 @@
+use crate::*;
+
+// A price change scheduled by the shop, it applies from `starts_at` until `ends_at`
+// or until `max_quantity` items have been sold at the sale price
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Sale {
+  pub price: Balance,
+  pub starts_at: Timestamp,
+  pub ends_at: Timestamp,
+  pub max_quantity: Option<u64>,
+  pub sold: u64,
+}
+
+impl Sale {
+  pub fn is_active(&self) -> bool {
+    let now = env::block_timestamp();
+    self.starts_at <= now
+      && now < self.ends_at
+      && self.max_quantity.iter().all(|&max_quantity| self.sold < max_quantity)
+  }
+}
+
+impl Product {
+  // How many of `quantity` items are sold at the sale price right now,
+  // variants with their own price are not on sale
+  pub fn sale_quantity(&self, sku: Option<&String>, quantity: u64) -> u64 {
+    match self.sale.as_ref() {
+      Some(_) if self.price_override(sku).is_some() => 0,
+      Some(sale) if sale.is_active() => match sale.max_quantity {
+        Some(max_quantity) => std::cmp::min(quantity, max_quantity - sale.sold),
+        None => quantity,
+      },
+      _ => 0,
+    }
+  }
+
+  // The sale never makes the product dearer, even if its price was lowered under the sale price since
+  pub fn current_price(&self) -> Balance {
+    match self.sale.as_ref() {
+      Some(sale) if sale.is_active() => std::cmp::min(sale.price, self.price),
+      _ => self.price,
+    }
+  }
+
+  // Total price of `quantity` items, the ones on sale are charged the sale price
+  pub fn purchase_price(&self, sku: Option<&String>, quantity: u64) -> Balance {
+    let sale_quantity = self.sale_quantity(sku, quantity);
+    let sale_price = self.current_price().checked_mul(sale_quantity as u128);
+    let regular_price = self.unit_price(sku).checked_mul((quantity - sale_quantity) as u128);
+    sale_price.zip(regular_price).and_then(|(sale, regular)| sale.checked_add(regular)).expect("Total price overflow")
+  }
+
+  // Take the items out of stock and count the ones sold at the sale price
+  pub(crate) fn sell(&mut self, sku: Option<&String>, quantity: u64) {
+    let sale_quantity = self.sale_quantity(sku, quantity);
+    if let Some(sale) = self.sale.as_mut() {
+      sale.sold += sale_quantity;
+    }
+    self.take_stock(sku, quantity);
+  }
+
+  // The product as shown to buyers, `price` is the sale price while the sale runs
+  pub fn with_current_price(mut self) -> Self {
+    self.price = self.current_price();
+    self
+  }
+}
+
+#[near_bindgen]
+impl Contract {
+  pub fn set_product_sale(
+    &mut self,
+    product_id: ProductId,
+    price: Balance,
+    starts_at: Timestamp,
+    ends_at: Timestamp,
+    max_quantity: Option<u64>,
+  ) -> Product {
+    self.assert_not_paused();
+    let mut product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+    assert!(price < product.price, "Sale price must be lower than the price");
+    assert!(starts_at < ends_at, "Sale must end after it starts");
+    assert!(env::block_timestamp() < ends_at, "Sale has already ended");
+    if let Some(max_quantity) = max_quantity {
+      assert!(max_quantity > 0, "Max quantity must be greater than 0");
+    }
+    let initial_storage_usage = env::storage_usage();
+
+    product.sale = Some(Sale { price, starts_at, ends_at, max_quantity, sold: 0 });
+    self.internal_update_product(&product);
+    self.internal_update_storage(&product.owner, initial_storage_usage);
+    product
+  }
+
+  pub fn cancel_product_sale(&mut self, product_id: ProductId) -> Product {
+    self.assert_not_paused();
+    let mut product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+    assert!(product.sale.is_some(), "Product has no sale");
+    let initial_storage_usage = env::storage_usage();
+
+    product.sale = None;
+    self.internal_update_product(&product);
+    self.internal_update_storage(&product.owner, initial_storage_usage);
+    product
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use near_sdk::test_utils::accounts;
+
+  use crate::test_utils::*;
+
+  #[test]
+  fn order_records_the_sale_price() {
+    let mut contract = setup_contract();
+    contract.set_product_sale("p1".to_string(), 80, 0, 100, None);
+
+    let order = buy(&mut contract, accounts(2), 2);
+    assert_eq!((order.price, order.amount), (80, 160));
+    assert_eq!(transfers(), vec![(accounts(2), 40)]);
+  }
+
+  #[test]
+  fn lower_price_wins_over_the_sale() {
+    let mut contract = setup_contract();
+    contract.set_product_sale("p1".to_string(), 80, 0, 100, None);
+    contract.update_product("p1".to_string(), None, None, Some(50), None, None, None);
+    assert_eq!(contract.get_product_by_id("p1".to_string()).price, 50);
+
+    let order = buy(&mut contract, accounts(2), 1);
+    assert_eq!((order.price, order.amount), (50, 50));
+  }
+
+  #[test]
+  fn cart_lines_share_the_sale() {
+    let mut contract = setup_contract();
+    for sku in ["S", "M"] {
+      contract.add_variant("p1".to_string(), sku.to_string(), BTreeMap::new(), None, 5);
+    }
+    contract.set_product_sale("p1".to_string(), 80, 0, 100, Some(3));
+
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_cart("p1".to_string(), 2, Some("S".to_string()));
+    contract.add_to_cart("p1".to_string(), 2, Some("M".to_string()));
+    set_context(accounts(2), 340);
+    let orders = contract.checkout(None);
+    assert_eq!(orders.iter().map(|order| (order.price, order.amount)).collect::<Vec<_>>(), vec![(80, 160), (90, 180)]);
+    assert_eq!(contract.get_product_by_id("p1".to_string()).sale.unwrap().sold, 3);
+  }
+}
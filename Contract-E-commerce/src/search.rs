@@ -0,0 +1,167 @@
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+use near_sdk::collections::TreeMap;
+
+use crate::*;
+
+// The longest word of a query needs this many characters, shorter prefixes match too much of the index
+pub const MIN_SEARCH_LENGTH: usize = 2;
+
+#[near_bindgen]
+impl Contract {
+  // Products with a name word starting with each word of `prefix`, e.g. "qua 3" matches "Quan 3 lo"
+  pub fn search_products(&self, prefix: String, from_index: Option<u64>, limit: Option<u64>) -> Vec<Product> {
+    let (from_index, limit) = pagination(from_index, limit);
+    let words = tokenize(&prefix);
+
+    search_index(&self.product_name_index, search_word(&words))
+      .map(|product_id| self.internal_get_product(&product_id))
+      .filter(|product| !product.delisted && matches_words(&product.name, &words))
+      .skip(from_index)
+      .take(limit)
+      .map(Product::with_current_price)
+      .collect()
+  }
+
+  pub fn search_shops(&self, prefix: String, from_index: Option<u64>, limit: Option<u64>) -> Vec<Shop> {
+    let (from_index, limit) = pagination(from_index, limit);
+    let words = tokenize(&prefix);
+
+    search_index(&self.shop_name_index, search_word(&words))
+      .map(|owner| self.internal_get_shop(&owner))
+      .filter(|shop| matches_words(&shop.name, &words))
+      .skip(from_index)
+      .take(limit)
+      .collect()
+  }
+}
+
+impl Contract {
+  pub(crate) fn internal_index_product_name(&mut self, product: &Product) {
+    for token in tokenize(&product.name) {
+      let mut product_ids = self.product_name_index.get(&token).unwrap_or_else(|| {
+        UnorderedSet::new(
+          StorageKey::ProductNameIndexInnerKey { token_hash: env::sha256_array(token.as_bytes()) }
+            .try_to_vec()
+            .unwrap(),
+        )
+      });
+      product_ids.insert(&product.product_id);
+      self.product_name_index.insert(&token, &product_ids);
+    }
+  }
+
+  pub(crate) fn internal_unindex_product_name(&mut self, product: &Product) {
+    for token in tokenize(&product.name) {
+      if let Some(mut product_ids) = self.product_name_index.get(&token) {
+        product_ids.remove(&product.product_id);
+        if product_ids.is_empty() {
+          self.product_name_index.remove(&token);
+        } else {
+          self.product_name_index.insert(&token, &product_ids);
+        }
+      }
+    }
+  }
+
+  pub(crate) fn internal_index_shop_name(&mut self, shop: &Shop) {
+    for token in tokenize(&shop.name) {
+      let mut owners = self.shop_name_index.get(&token).unwrap_or_else(|| {
+        UnorderedSet::new(
+          StorageKey::ShopNameIndexInnerKey { token_hash: env::sha256_array(token.as_bytes()) }.try_to_vec().unwrap(),
+        )
+      });
+      owners.insert(&shop.owner);
+      self.shop_name_index.insert(&token, &owners);
+    }
+  }
+
+  pub(crate) fn internal_unindex_shop_name(&mut self, shop: &Shop) {
+    for token in tokenize(&shop.name) {
+      if let Some(mut owners) = self.shop_name_index.get(&token) {
+        owners.remove(&shop.owner);
+        if owners.is_empty() {
+          self.shop_name_index.remove(&token);
+        } else {
+          self.shop_name_index.insert(&token, &owners);
+        }
+      }
+    }
+  }
+}
+
+// Lowercased words of a name, without duplicates
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+  let mut tokens: Vec<String> = text
+    .to_lowercase()
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|token| !token.is_empty())
+    .map(String::from)
+    .collect();
+  tokens.sort();
+  tokens.dedup();
+  tokens
+}
+
+fn matches_words(name: &str, words: &[String]) -> bool {
+  let tokens = tokenize(name);
+  words.iter().all(|word| tokens.iter().any(|token| token.starts_with(word.as_str())))
+}
+
+// The word of the query looked up in the index, the longest one matches the fewest items
+fn search_word(words: &[String]) -> &str {
+  let word = words.iter().max_by_key(|word| word.chars().count()).map(String::as_str).unwrap_or_default();
+  assert!(word.chars().count() >= MIN_SEARCH_LENGTH, "Search for at least {} characters", MIN_SEARCH_LENGTH);
+  word
+}
+
+// Items with a token starting with `word`, without duplicates.
+// Read lazily so a page only loads the sets it needs.
+fn search_index<'a, T>(index: &'a TreeMap<String, UnorderedSet<T>>, word: &'a str) -> impl Iterator<Item = T> + 'a
+where
+  T: BorshSerialize + BorshDeserialize + Ord + Clone + 'a,
+{
+  let mut seen = BTreeSet::new();
+  index
+    .range((Bound::Included(word.to_string()), Bound::Unbounded))
+    .take_while(move |(token, _)| token.starts_with(word))
+    .flat_map(|(_, items)| (0..items.len()).filter_map(move |i| items.as_vector().get(i)))
+    .filter(move |item| seen.insert(item.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  #[test]
+  fn search_pages_without_duplicates() {
+    let mut contract = setup_contract();
+    set_context(accounts(1), 0);
+    for (product_id, name) in [("p2", "Quan quanh"), ("p3", "Quan jean"), ("p4", "Ao")] {
+      contract.new_product(product_id.to_string(), name.to_string(), 1, 1, "desc".to_string(), None, None, None);
+    }
+
+    let page = |from_index, limit| -> Vec<ProductId> {
+      contract
+        .search_products("qua".to_string(), Some(from_index), Some(limit))
+        .into_iter()
+        .map(|product| product.product_id)
+        .collect()
+    };
+    let all = page(0, 10);
+    assert_eq!(all.len(), 3);
+    assert_eq!([page(0, 2), page(2, 2)].concat(), all);
+    assert_eq!(contract.search_products("j qua".to_string(), None, None)[0].product_id, "p3");
+  }
+
+  #[test]
+  #[should_panic(expected = "Search for at least 2 characters")]
+  fn search_needs_a_longer_prefix() {
+    let contract = setup_contract();
+    contract.search_products("q".to_string(), None, None);
+  }
+}
@@ -0,0 +1,190 @@
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Shop {
+  pub owner: AccountId,
+  pub name: String,
+  pub desc: String,
+  pub total_product: u64,
+  pub total_orders: u64,
+  pub completed_orders: u64,
+  pub cancelled_orders: u64,
+  pub rating_sum: u64,
+  pub rating_count: u64,
+  pub dispute_losses: u64, // orders refunded after the buyer opened a claim
+  pub return_window: u64,  // buyers can ask for a return until this long after delivery
+}
+
+#[near_bindgen]
+impl Contract {
+  pub fn update_shop(&mut self, name: Option<String>, desc: Option<String>) -> Shop {
+    self.assert_not_paused();
+    let owner = env::signer_account_id();
+    let mut shop = self.internal_get_shop(&owner);
+    let initial_storage_usage = env::storage_usage();
+
+    if let Some(name) = name {
+      shop.name = name
+    }
+    if let Some(desc) = desc {
+      shop.desc = desc
+    }
+
+    self.internal_update_shop(&shop);
+    self.internal_update_storage(&owner, initial_storage_usage);
+    Event::ShopUpdate(vec![(&shop).into()]).emit();
+    shop
+  }
+
+  // Delist every product of the shop, remove it and give back its storage deposit
+  pub fn close_shop(&mut self) -> Shop {
+    self.assert_not_paused();
+    let owner = env::signer_account_id();
+    let shop = self.internal_get_shop(&owner);
+    let initial_storage_usage = env::storage_usage();
+
+    for product_id in self.internal_shop_product_ids(&owner) {
+      self.internal_remove_product(&product_id);
+    }
+    self.internal_remove_shop(&owner);
+    self.pending_shop_transfers.remove(&owner);
+    self.internal_refund_storage(&owner, initial_storage_usage);
+    Event::ShopClose(vec![(&shop).into()]).emit();
+
+    shop
+  }
+
+  // First step of the ownership handoff, the new owner has to call `accept_shop`
+  pub fn transfer_shop(&mut self, new_owner: AccountId) {
+    self.assert_not_paused();
+    let owner = env::signer_account_id();
+    self.internal_get_shop(&owner);
+    assert_ne!(owner, new_owner, "You already own this shop");
+    assert!(self.internal_shop(&new_owner).is_none(), "{} already has a shop", new_owner);
+
+    self.pending_shop_transfers.insert(&owner, &new_owner);
+  }
+
+  pub fn cancel_shop_transfer(&mut self) {
+    self.assert_not_paused();
+    let owner = env::signer_account_id();
+    self.pending_shop_transfers.remove(&owner).expect("There is no pending transfer");
+  }
+
+  // Second step: the new owner takes the shop and all of its products, which keep their indexes,
+  // reservations and wishlist entries. The storage of the shop is charged to the new owner
+  // and refunded to the previous one.
+  pub fn accept_shop(&mut self, owner: AccountId) -> Shop {
+    self.assert_not_paused();
+    let new_owner = env::signer_account_id();
+    self.assert_not_banned(&new_owner);
+    let pending_owner = self.pending_shop_transfers.get(&owner).expect("There is no pending transfer");
+    assert_eq!(pending_owner, new_owner, "The shop is not transferred to you");
+    assert!(self.internal_shop(&new_owner).is_none(), "You already have a shop");
+    self.pending_shop_transfers.remove(&owner);
+
+    let initial_storage_usage = env::storage_usage();
+    // Taken out and stored again so the storage moves from one owner to the other
+    let products: Vec<(Product, u128)> = self
+      .internal_shop_product_ids(&owner)
+      .iter()
+      .map(|product_id| self.internal_unstore_product(product_id))
+      .collect();
+    let mut shop = self.internal_remove_shop(&owner);
+    self.internal_refund_storage(&owner, initial_storage_usage);
+
+    let initial_storage_usage = env::storage_usage();
+    shop.owner = new_owner.clone();
+    self.internal_add_shop(&shop);
+    for (mut product, index) in products {
+      product.owner = new_owner.clone();
+      self.internal_store_product(&product, index);
+    }
+    self.internal_update_storage(&new_owner, initial_storage_usage);
+    Event::ShopTransfer(vec![ShopTransferData { old_owner: owner, new_owner }]).emit();
+
+    shop
+  }
+
+  pub fn get_pending_shop_transfer(&self, owner: AccountId) -> Option<AccountId> {
+    self.pending_shop_transfers.get(&owner)
+  }
+}
+
+// Shops are stored in `shops` and `all_shops` and indexed by name words, these methods keep them in sync
+impl Contract {
+  pub(crate) fn internal_get_shop(&self, owner: &AccountId) -> Shop {
+    self.internal_shop(owner).expect("Shop not found")
+  }
+
+  // A record that is not a `Shop` is a shop of the v1 contract that `migrate` could not find
+  pub(crate) fn internal_shop(&self, owner: &AccountId) -> Option<Shop> {
+    let prefix: Vec<u8> = retype(&self.shops);
+    let shop = env::storage_read(&[prefix, owner.try_to_vec().unwrap()].concat())?;
+    Shop::try_from_slice(&shop).ok()
+  }
+
+  pub(crate) fn internal_add_shop(&mut self, shop: &Shop) {
+    assert!(self.internal_shop(&shop.owner).is_none(), "Shop already exists");
+
+    self.total_shops += 1;
+    // Replaces a v1 shop without reading it
+    self.shops.insert_raw(&shop.owner.try_to_vec().unwrap(), &shop.try_to_vec().unwrap());
+    self.all_shops.insert(&self.total_shops, shop);
+    self.shop_index.insert(&shop.owner, &self.total_shops);
+    self.internal_index_shop_name(shop);
+  }
+
+  pub(crate) fn internal_update_shop(&mut self, shop: &Shop) {
+    let index = self.shop_index.get(&shop.owner).expect("Shop not found");
+    let old_shop = self.internal_get_shop(&shop.owner);
+    if old_shop.name != shop.name {
+      self.internal_unindex_shop_name(&old_shop);
+      self.internal_index_shop_name(shop);
+    }
+    self.shops.insert(&shop.owner, shop);
+    self.all_shops.insert(&index, shop);
+  }
+
+  pub(crate) fn internal_remove_shop(&mut self, owner: &AccountId) -> Shop {
+    let shop = self.shops.remove(owner).expect("Shop not found");
+    let index = self.shop_index.remove(owner).expect("Shop not found");
+    self.all_shops.remove(&index);
+    self.internal_unindex_shop_name(&shop);
+    shop
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::{accounts, get_logs};
+
+  use crate::test_utils::*;
+
+  #[test]
+  fn accepted_shop_keeps_its_products() {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_wishlist("later".to_string(), "p1".to_string());
+    set_context(accounts(1), 0);
+    contract.transfer_shop(accounts(3));
+    register(&mut contract, accounts(3));
+
+    set_context(accounts(3), 0);
+    contract.accept_shop(accounts(1));
+    let logs = get_logs();
+    assert_eq!(logs.len(), 1);
+    assert!(logs[0].contains(r#""event":"shop_transfer""#));
+
+    assert_eq!(contract.get_product_by_id("p1".to_string()).owner, accounts(3));
+    assert_eq!(contract.get_products_by_owner(accounts(3), None, None).len(), 1);
+    assert_eq!(contract.get_total_products_by_owner(accounts(1)), 0);
+    assert_eq!(contract.get_total_products(), 1);
+    assert_eq!(contract.search_products("quan".to_string(), None, None)[0].owner, accounts(3));
+    assert_eq!(contract.get_total_wishlisters("p1".to_string()), 1);
+    assert_eq!(contract.storage_accounts.get(&accounts(1)).unwrap().used, 0);
+    assert!(contract.storage_accounts.get(&accounts(3)).unwrap().used > 0);
+  }
+}
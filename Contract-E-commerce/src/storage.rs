@@ -0,0 +1,257 @@
+use near_contract_standards::storage_management::{StorageBalance, StorageBalanceBounds, StorageManagement};
+use near_sdk::assert_one_yocto;
+use near_sdk::json_types::U128;
+
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StorageAccount {
+  pub total: Balance,     // deposited NEAR
+  pub used: StorageUsage, // bytes taken by the shop and products of the account
+}
+
+// As NEP-145 requires, deposits and refunds go to the predecessor, not the signer
+#[near_bindgen]
+impl StorageManagement for Contract {
+  #[payable]
+  fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>) -> StorageBalance {
+    let amount = env::attached_deposit();
+    let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+    let registration_only = registration_only.unwrap_or(false);
+    let min_balance = self.storage_balance_bounds().min.0;
+
+    match self.storage_accounts.get(&account_id) {
+      Some(mut account) => {
+        if registration_only {
+          if amount > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(amount);
+          }
+        } else {
+          account.total += amount;
+          self.storage_accounts.insert(&account_id, &account);
+        }
+      }
+      None => {
+        assert!(amount >= min_balance, "The attached deposit is less than the minimum storage balance");
+        let total = if registration_only { min_balance } else { amount };
+        self.storage_accounts.insert(&account_id, &StorageAccount { total, used: 0 });
+        if amount > total {
+          Promise::new(env::predecessor_account_id()).transfer(amount - total);
+        }
+      }
+    }
+
+    self.storage_balance_of(account_id).unwrap()
+  }
+
+  #[payable]
+  fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+    assert_one_yocto();
+    let account_id = env::predecessor_account_id();
+    let mut account = self.storage_accounts.get(&account_id).expect("The account is not registered");
+    let available = self.internal_storage_available(&account);
+    let amount = amount.map(|amount| amount.0).unwrap_or(available);
+    assert!(amount <= available, "The amount is greater than the available storage balance");
+
+    if amount > 0 {
+      account.total -= amount;
+      self.storage_accounts.insert(&account_id, &account);
+      Promise::new(account_id.clone()).transfer(amount);
+    }
+
+    self.storage_balance_of(account_id).unwrap()
+  }
+
+  // Shops and products are not removed here, so `force` is not supported
+  #[payable]
+  fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+    assert_one_yocto();
+    assert!(!force.unwrap_or(false), "Force unregister is not supported");
+    let account_id = env::predecessor_account_id();
+
+    match self.storage_accounts.get(&account_id) {
+      Some(account) => {
+        assert_eq!(account.used, 0, "Can't unregister the account while it still uses storage");
+        self.storage_accounts.remove(&account_id);
+        Promise::new(account_id).transfer(account.total);
+        true
+      }
+      None => false,
+    }
+  }
+
+  fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+    StorageBalanceBounds { min: U128(self.account_storage_usage as u128 * env::storage_byte_cost()), max: None }
+  }
+
+  fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+    self.storage_accounts.get(&account_id).map(|account| StorageBalance {
+      total: U128(account.total),
+      available: U128(self.internal_storage_available(&account)),
+    })
+  }
+}
+
+impl Contract {
+  // Bytes taken by the storage entry of an account with the longest account id
+  pub(crate) fn measure_account_storage_usage(&mut self) {
+    let initial_storage_usage = env::storage_usage();
+    let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
+    self.storage_accounts.insert(&tmp_account_id, &StorageAccount { total: 0, used: 0 });
+    self.account_storage_usage = env::storage_usage() - initial_storage_usage;
+    self.storage_accounts.remove(&tmp_account_id);
+  }
+
+  fn internal_storage_available(&self, account: &StorageAccount) -> Balance {
+    let locked = (self.account_storage_usage + account.used) as u128 * env::storage_byte_cost();
+    account.total.saturating_sub(locked)
+  }
+
+  // Charge (or give back) the storage used since `initial_storage_usage` to the account
+  pub(crate) fn internal_update_storage(&mut self, account_id: &AccountId, initial_storage_usage: StorageUsage) {
+    let storage_usage = env::storage_usage();
+    if storage_usage >= initial_storage_usage {
+      self.internal_charge_storage(account_id, storage_usage - initial_storage_usage);
+    } else {
+      assert!(self.storage_accounts.contains_key(account_id), "Register your account with storage_deposit first");
+      self.internal_free_storage(account_id, initial_storage_usage - storage_usage);
+    }
+  }
+
+  // Charge `bytes` to the account, its deposit has to cover them
+  pub(crate) fn internal_charge_storage(&mut self, account_id: &AccountId, bytes: StorageUsage) {
+    let mut account = self.storage_accounts.get(account_id).expect("Register your account with storage_deposit first");
+    account.used += bytes;
+    let locked = (self.account_storage_usage + account.used) as u128 * env::storage_byte_cost();
+    assert!(
+      account.total >= locked,
+      "Not enough storage deposit, attach at least {} more with storage_deposit",
+      locked - account.total
+    );
+    self.storage_accounts.insert(account_id, &account);
+  }
+
+  // Give `bytes` back to the account, if it is still registered
+  pub(crate) fn internal_free_storage(&mut self, account_id: &AccountId, bytes: StorageUsage) {
+    if let Some(mut account) = self.storage_accounts.get(account_id) {
+      account.used = account.used.saturating_sub(bytes);
+      self.storage_accounts.insert(account_id, &account);
+    }
+  }
+
+  // Give back the deposit of the storage freed since `initial_storage_usage`
+  pub(crate) fn internal_refund_storage(&mut self, account_id: &AccountId, initial_storage_usage: StorageUsage) {
+    let freed = initial_storage_usage.saturating_sub(env::storage_usage());
+    self.internal_update_storage(account_id, initial_storage_usage);
+
+    let mut account = self.storage_accounts.get(account_id).unwrap();
+    let refund = std::cmp::min(freed as u128 * env::storage_byte_cost(), account.total);
+    if refund > 0 {
+      account.total -= refund;
+      self.storage_accounts.insert(account_id, &account);
+      Promise::new(account_id.clone()).transfer(refund);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  fn setup_storage() -> (Contract, Balance) {
+    set_context(accounts(0), 0);
+    let contract = Contract::init();
+    let min_balance = contract.storage_balance_bounds().min.0;
+    (contract, min_balance)
+  }
+
+  #[test]
+  fn deposit_withdraw_and_unregister() {
+    let (mut contract, min_balance) = setup_storage();
+    set_context(accounts(2), min_balance + 100);
+    let balance = contract.storage_deposit(None, None);
+    assert_eq!((balance.total.0, balance.available.0), (min_balance + 100, 100));
+
+    set_context(accounts(2), 1);
+    let balance = contract.storage_withdraw(Some(U128(40)));
+    assert_eq!(balance.available.0, 60);
+    assert_eq!(transfers(), vec![(accounts(2), 40)]);
+
+    set_context(accounts(2), 1);
+    assert!(contract.storage_unregister(None));
+    assert_eq!(transfers(), vec![(accounts(2), min_balance + 60)]);
+    assert!(contract.storage_balance_of(accounts(2)).is_none());
+  }
+
+  #[test]
+  fn registration_only_refunds_the_rest() {
+    let (mut contract, min_balance) = setup_storage();
+    set_context(accounts(2), min_balance + 100);
+    let balance = contract.storage_deposit(None, Some(true));
+    assert_eq!(balance.total.0, min_balance);
+    assert_eq!(transfers(), vec![(accounts(2), 100)]);
+  }
+
+  #[test]
+  #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+  fn deposit_below_the_minimum() {
+    let (mut contract, min_balance) = setup_storage();
+    set_context(accounts(2), min_balance - 1);
+    contract.storage_deposit(None, None);
+  }
+
+  #[test]
+  #[should_panic(expected = "The amount is greater than the available storage balance")]
+  fn withdraw_over_the_available_balance() {
+    let (mut contract, min_balance) = setup_storage();
+    set_context(accounts(2), min_balance + 100);
+    contract.storage_deposit(None, None);
+    set_context(accounts(2), 1);
+    contract.storage_withdraw(Some(U128(101)));
+  }
+
+  #[test]
+  #[should_panic(expected = "Can't unregister the account while it still uses storage")]
+  fn unregister_with_storage_used() {
+    let mut contract = setup_contract();
+    set_context(accounts(1), 1);
+    contract.storage_unregister(None);
+  }
+
+  #[test]
+  #[should_panic(expected = "Force unregister is not supported")]
+  fn force_unregister() {
+    let (mut contract, min_balance) = setup_storage();
+    set_context(accounts(2), min_balance);
+    contract.storage_deposit(None, None);
+    set_context(accounts(2), 1);
+    contract.storage_unregister(Some(true));
+  }
+
+  #[test]
+  #[should_panic(expected = "Not enough storage deposit")]
+  fn charge_over_the_deposit() {
+    let (mut contract, min_balance) = setup_storage();
+    set_context(accounts(2), min_balance);
+    contract.storage_deposit(None, None);
+    contract.internal_charge_storage(&accounts(2), 1);
+  }
+
+  #[test]
+  fn free_gives_back_what_was_charged() {
+    let (mut contract, _) = setup_storage();
+    set_context(accounts(2), ONE_NEAR);
+    contract.storage_deposit(None, None);
+    contract.internal_charge_storage(&accounts(2), 100);
+    let available = contract.storage_balance_of(accounts(2)).unwrap().available.0;
+    assert_eq!(available, ONE_NEAR - contract.storage_balance_bounds().min.0 - 100 * env::storage_byte_cost());
+
+    contract.internal_free_storage(&accounts(2), 100);
+    contract.internal_free_storage(&accounts(3), 100);
+    assert_eq!(contract.storage_accounts.get(&accounts(2)).unwrap().used, 0);
+    assert!(contract.storage_accounts.get(&accounts(3)).is_none());
+  }
+}
@@ -0,0 +1,78 @@
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::mock::VmAction;
+use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+use near_sdk::testing_env;
+
+use crate::*;
+
+pub const ONE_NEAR: Balance = 10u128.pow(24);
+
+// The caller is both the signer and the predecessor
+pub fn set_context(caller: AccountId, deposit: Balance) {
+  set_context_at(caller, deposit, 0);
+}
+
+pub fn set_context_at(caller: AccountId, deposit: Balance, timestamp: Timestamp) {
+  let mut builder = VMContextBuilder::new();
+  builder
+    .current_account_id(accounts(0))
+    .signer_account_id(caller.clone())
+    .predecessor_account_id(caller)
+    .attached_deposit(deposit)
+    .block_timestamp(timestamp);
+  testing_env!(builder.build());
+}
+
+pub fn register(contract: &mut Contract, account_id: AccountId) {
+  set_context(account_id, ONE_NEAR);
+  contract.storage_deposit(None, None);
+}
+
+// `accounts(0)` owns the platform, `accounts(1)` has a shop selling 10 "p1" at 100 yoctoNEAR
+pub fn setup_contract() -> Contract {
+  set_context(accounts(0), 0);
+  let mut contract = Contract::init();
+  register(&mut contract, accounts(1));
+  set_context(accounts(1), 0);
+  contract.new_shop("Shop".to_string(), "desc".to_string());
+  contract.new_product("p1".to_string(), "Quan".to_string(), 10, 100, "desc".to_string(), None, None, None);
+  contract
+}
+
+pub fn buy(contract: &mut Contract, buyer: AccountId, quantity: u64) -> Order {
+  set_context(buyer, 100 * quantity as u128);
+  contract.buy_product("p1".to_string(), quantity, None, None)
+}
+
+// NEAR sent by the contract since the context was set
+pub fn transfers() -> Vec<(AccountId, Balance)> {
+  get_created_receipts()
+    .into_iter()
+    .flat_map(|receipt| {
+      let receiver_id = receipt.receiver_id;
+      receipt.actions.into_iter().filter_map(move |action| match action {
+        VmAction::Transfer { deposit } => Some((receiver_id.clone(), deposit)),
+        _ => None,
+      })
+    })
+    .collect()
+}
+
+// `ft_transfer` calls made by the contract since the context was set, as (token, receiver, amount)
+pub fn ft_transfers() -> Vec<(AccountId, AccountId, Balance)> {
+  get_created_receipts()
+    .into_iter()
+    .flat_map(|receipt| {
+      let token_id = receipt.receiver_id;
+      receipt.actions.into_iter().filter_map(move |action| match action {
+        VmAction::FunctionCall { function_name, args, .. } if function_name == "ft_transfer" => {
+          let args: near_sdk::serde_json::Value = near_sdk::serde_json::from_slice(&args).unwrap();
+          let receiver_id = args["receiver_id"].as_str().unwrap().parse().unwrap();
+          let amount = args["amount"].as_str().unwrap().parse().unwrap();
+          Some((token_id.clone(), receiver_id, amount))
+        }
+        _ => None,
+      })
+    })
+    .collect()
+}
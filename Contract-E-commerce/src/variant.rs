@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+
+use crate::*;
+
+pub const MAX_SKU_LENGTH: usize = 64;
+
+// A size, color, ... of a product with its own stock, bought by its `sku`
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Variant {
+  pub sku: String,
+  pub attributes: BTreeMap<String, String>, // e.g. "size" -> "XL"
+  pub price: Option<Balance>,               // the product price is used if not set
+  pub total_supply: u64,
+}
+
+impl Product {
+  pub fn variant(&self, sku: &str) -> &Variant {
+    self.variants.iter().find(|variant| variant.sku == sku).expect("Variant not found")
+  }
+
+  fn variant_mut(&mut self, sku: &str) -> &mut Variant {
+    self.variants.iter_mut().find(|variant| variant.sku == sku).expect("Variant not found")
+  }
+
+  // Price of the variant if it overrides the product price
+  pub fn price_override(&self, sku: Option<&String>) -> Option<Balance> {
+    sku.and_then(|sku| self.variant(sku).price)
+  }
+
+  pub fn unit_price(&self, sku: Option<&String>) -> Balance {
+    self.price_override(sku).unwrap_or(self.price)
+  }
+
+  pub fn stock(&self, sku: Option<&String>) -> u64 {
+    match sku {
+      Some(sku) => self.variant(sku).total_supply,
+      None => {
+        assert!(self.variants.is_empty(), "Choose a variant of {}", self.product_id);
+        self.total_supply
+      }
+    }
+  }
+
+  pub(crate) fn take_stock(&mut self, sku: Option<&String>, quantity: u64) {
+    if let Some(sku) = sku {
+      self.variant_mut(sku).total_supply -= quantity;
+    }
+    self.total_supply -= quantity;
+  }
+
+  // Put items back in stock, nothing is added if their variant has been removed since
+  pub(crate) fn restock(&mut self, sku: Option<&String>, quantity: u64) {
+    if let Some(sku) = sku {
+      match self.variants.iter_mut().find(|variant| &variant.sku == sku) {
+        Some(variant) => variant.total_supply += quantity,
+        None => return,
+      }
+    }
+    self.total_supply += quantity;
+  }
+
+  pub(crate) fn set_stock(&mut self, sku: Option<&String>, total_supply: u64) {
+    match sku {
+      Some(sku) => {
+        self.variant_mut(sku).total_supply = total_supply;
+        self.sum_variant_supply();
+      }
+      None => {
+        assert!(self.variants.is_empty(), "Update the stock of each variant");
+        self.total_supply = total_supply;
+      }
+    }
+  }
+
+  // With variants the stock of the product is the stock of all its variants
+  fn sum_variant_supply(&mut self) {
+    self.total_supply = self.variants.iter().map(|variant| variant.total_supply).sum();
+  }
+}
+
+#[near_bindgen]
+impl Contract {
+  // Once a product has variants its own `total_supply` is the sum of the variants
+  // and every purchase has to choose one
+  pub fn add_variant(
+    &mut self,
+    product_id: ProductId,
+    sku: String,
+    attributes: BTreeMap<String, String>,
+    price: Option<Balance>,
+    total_supply: u64,
+  ) -> Product {
+    self.assert_not_paused();
+    let mut product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+    assert!(sku.len() <= MAX_SKU_LENGTH, "SKU is longer than {} bytes", MAX_SKU_LENGTH);
+    assert!(product.variants.iter().all(|variant| variant.sku != sku), "Variant already exists");
+    let initial_storage_usage = env::storage_usage();
+
+    product.variants.push(Variant { sku, attributes, price, total_supply });
+    product.sum_variant_supply();
+    self.internal_update_product(&product);
+    self.internal_update_storage(&product.owner, initial_storage_usage);
+    product
+  }
+
+  pub fn update_variant(
+    &mut self,
+    product_id: ProductId,
+    sku: String,
+    attributes: Option<BTreeMap<String, String>>,
+    price: Option<Balance>,
+    total_supply: Option<u64>,
+  ) -> Product {
+    self.assert_not_paused();
+    let mut product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+    let initial_storage_usage = env::storage_usage();
+
+    let variant = product.variant_mut(&sku);
+    if let Some(attributes) = attributes {
+      variant.attributes = attributes
+    }
+    if let Some(price) = price {
+      variant.price = Some(price)
+    }
+    if let Some(total_supply) = total_supply {
+      variant.total_supply = total_supply
+    }
+    product.sum_variant_supply();
+
+    self.internal_update_product(&product);
+    self.internal_update_storage(&product.owner, initial_storage_usage);
+    product
+  }
+
+  pub fn remove_variant(&mut self, product_id: ProductId, sku: String) -> Product {
+    self.assert_not_paused();
+    let mut product = self.internal_get_product(&product_id);
+    assert_eq!(product.owner, env::signer_account_id(), "Unauthorized");
+    let initial_storage_usage = env::storage_usage();
+
+    let index = product.variants.iter().position(|variant| variant.sku == sku).expect("Variant not found");
+    product.variants.remove(index);
+    product.sum_variant_supply();
+    self.internal_update_product(&product);
+    self.internal_update_storage(&product.owner, initial_storage_usage);
+    product
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  // "p1" in size "S", 3 at the product price, and size "L", 5 at 150
+  fn setup_variants() -> Contract {
+    let mut contract = setup_contract();
+    set_context(accounts(1), 0);
+    let size = |size: &str| BTreeMap::from([("size".to_string(), size.to_string())]);
+    contract.add_variant("p1".to_string(), "S".to_string(), size("S"), None, 3);
+    contract.add_variant("p1".to_string(), "L".to_string(), size("L"), Some(150), 5);
+    contract
+  }
+
+  fn buy_variant(contract: &mut Contract, sku: &str, quantity: u64, deposit: Balance) -> Order {
+    register(contract, accounts(2));
+    set_context(accounts(2), deposit);
+    contract.buy_product("p1".to_string(), quantity, None, Some(sku.to_string()))
+  }
+
+  #[test]
+  fn product_stock_is_the_sum_of_its_variants() {
+    let contract = setup_variants();
+    let product = contract.get_product_by_id("p1".to_string());
+    assert_eq!(product.total_supply, 8);
+    assert_eq!(product.stock(Some(&"S".to_string())), 3);
+    assert_eq!(product.variant("L").attributes.get("size"), Some(&"L".to_string()));
+  }
+
+  #[test]
+  fn variant_price_overrides_the_product_price() {
+    let contract = setup_variants();
+    let product = contract.get_product_by_id("p1".to_string());
+    assert_eq!(product.unit_price(Some(&"S".to_string())), 100);
+    assert_eq!(product.unit_price(Some(&"L".to_string())), 150);
+    assert_eq!(product.price_override(Some(&"S".to_string())), None);
+    assert_eq!(product.unit_price(None), 100);
+  }
+
+  #[test]
+  fn purchase_takes_the_stock_of_its_variant() {
+    let mut contract = setup_variants();
+    let order = buy_variant(&mut contract, "L", 2, 300);
+    assert_eq!((order.sku, order.price, order.amount), (Some("L".to_string()), 150, 300));
+
+    let product = contract.get_product_by_id("p1".to_string());
+    assert_eq!((product.variant("S").total_supply, product.variant("L").total_supply), (3, 3));
+    assert_eq!(product.total_supply, 6);
+  }
+
+  #[test]
+  fn update_and_remove_variants() {
+    let mut contract = setup_variants();
+    set_context(accounts(1), 0);
+    let product = contract.update_variant("p1".to_string(), "S".to_string(), None, Some(90), Some(10));
+    assert_eq!((product.unit_price(Some(&"S".to_string())), product.total_supply), (90, 15));
+
+    let product = contract.remove_variant("p1".to_string(), "L".to_string());
+    assert_eq!(product.variants.len(), 1);
+    assert_eq!(product.total_supply, 10);
+    assert_eq!(contract.get_product_by_id("p1".to_string()).total_supply, 10);
+  }
+
+  #[test]
+  fn restock_skips_removed_variants() {
+    let contract = setup_variants();
+    let mut product = contract.get_product_by_id("p1".to_string());
+    product.restock(Some(&"S".to_string()), 2);
+    product.restock(Some(&"XL".to_string()), 2);
+    assert_eq!((product.variant("S").total_supply, product.total_supply), (5, 10));
+  }
+
+  #[test]
+  #[should_panic(expected = "Choose a variant of p1")]
+  fn purchase_has_to_choose_a_variant() {
+    let contract = setup_variants();
+    contract.get_product_by_id("p1".to_string()).stock(None);
+  }
+
+  #[test]
+  #[should_panic(expected = "Not enough p1 in stock")]
+  fn purchase_over_the_variant_stock() {
+    let mut contract = setup_variants();
+    buy_variant(&mut contract, "S", 4, 400);
+  }
+
+  #[test]
+  #[should_panic(expected = "Variant already exists")]
+  fn sku_is_unique_per_product() {
+    let mut contract = setup_variants();
+    set_context(accounts(1), 0);
+    contract.add_variant("p1".to_string(), "S".to_string(), BTreeMap::new(), None, 1);
+  }
+
+  #[test]
+  #[should_panic(expected = "Update the stock of each variant")]
+  fn product_stock_can_not_be_set_directly() {
+    let mut contract = setup_variants();
+    set_context(accounts(1), 0);
+    contract.update_product("p1".to_string(), None, Some(20), None, None, None, None);
+  }
+
+  #[test]
+  #[should_panic(expected = "Unauthorized")]
+  fn only_the_owner_adds_variants() {
+    let mut contract = setup_contract();
+    set_context(accounts(2), 0);
+    contract.add_variant("p1".to_string(), "S".to_string(), BTreeMap::new(), None, 1);
+  }
+}
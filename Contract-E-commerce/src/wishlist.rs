@@ -0,0 +1,227 @@
+use crate::*;
+
+pub const MAX_WISHLISTS: usize = 20;
+pub const MAX_WISHLIST_PRODUCTS: usize = 100;
+pub const MAX_WISHLIST_NAME_LENGTH: usize = 50;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Wishlist {
+  pub name: String, // e.g. "wishlist", "saved for later"
+  pub product_ids: Vec<ProductId>,
+}
+
+#[near_bindgen]
+impl Contract {
+  // The wishlist is created on its first product.
+  // Wishlists are charged to the storage deposit of their account.
+  pub fn add_to_wishlist(&mut self, name: String, product_id: ProductId) -> Wishlist {
+    self.assert_not_paused();
+    self.internal_get_product(&product_id);
+    let account_id = env::signer_account_id();
+    let initial_storage_usage = env::storage_usage();
+
+    let mut wishlists = self.wishlists.get(&account_id).unwrap_or_default();
+    let index = match wishlists.iter().position(|wishlist| wishlist.name == name) {
+      Some(index) => index,
+      None => {
+        assert!(wishlists.len() < MAX_WISHLISTS, "You can have at most {} wishlists", MAX_WISHLISTS);
+        assert!(!name.is_empty(), "Give the wishlist a name");
+        assert!(name.len() <= MAX_WISHLIST_NAME_LENGTH, "Name is longer than {} bytes", MAX_WISHLIST_NAME_LENGTH);
+        wishlists.push(Wishlist { name, product_ids: Vec::new() });
+        wishlists.len() - 1
+      }
+    };
+    let wishlist = &mut wishlists[index];
+    assert!(!wishlist.product_ids.contains(&product_id), "Product is already in {}", wishlist.name);
+    assert!(
+      wishlist.product_ids.len() < MAX_WISHLIST_PRODUCTS,
+      "A wishlist holds at most {} products",
+      MAX_WISHLIST_PRODUCTS
+    );
+    wishlist.product_ids.push(product_id.clone());
+    let wishlist = wishlist.clone();
+
+    self.wishlists.insert(&account_id, &wishlists);
+    let mut accounts = self.wishlisted_by.get(&product_id).unwrap_or_else(|| {
+      UnorderedSet::new(
+        StorageKey::WishlistedByInnerKey { product_hash: env::sha256_array(product_id.as_bytes()) }
+          .try_to_vec()
+          .unwrap(),
+      )
+    });
+    accounts.insert(&account_id);
+    self.wishlisted_by.insert(&product_id, &accounts);
+    self.internal_update_storage(&account_id, initial_storage_usage);
+
+    wishlist
+  }
+
+  pub fn remove_from_wishlist(&mut self, name: String, product_id: ProductId) -> Wishlist {
+    self.assert_not_paused();
+    let account_id = env::signer_account_id();
+    let initial_storage_usage = env::storage_usage();
+
+    let mut wishlists = self.wishlists.get(&account_id).unwrap_or_default();
+    let wishlist = wishlists.iter_mut().find(|wishlist| wishlist.name == name).expect("Wishlist not found");
+    let index = wishlist.product_ids.iter().position(|id| id == &product_id).expect("Product is not in the wishlist");
+    wishlist.product_ids.remove(index);
+    let wishlist = wishlist.clone();
+
+    self.wishlists.insert(&account_id, &wishlists);
+    self.internal_unindex_wishlisted(&account_id, &wishlists, &product_id);
+    self.internal_update_storage(&account_id, initial_storage_usage);
+    wishlist
+  }
+
+  pub fn delete_wishlist(&mut self, name: String) {
+    self.assert_not_paused();
+    let account_id = env::signer_account_id();
+    let initial_storage_usage = env::storage_usage();
+
+    let mut wishlists = self.wishlists.get(&account_id).unwrap_or_default();
+    let index = wishlists.iter().position(|wishlist| wishlist.name == name).expect("Wishlist not found");
+    let wishlist = wishlists.remove(index);
+
+    if wishlists.is_empty() {
+      self.wishlists.remove(&account_id);
+    } else {
+      self.wishlists.insert(&account_id, &wishlists);
+    }
+    for product_id in wishlist.product_ids.iter() {
+      self.internal_unindex_wishlisted(&account_id, &wishlists, product_id);
+    }
+    self.internal_update_storage(&account_id, initial_storage_usage);
+  }
+
+  pub fn get_wishlists(&self, account_id: AccountId) -> Vec<Wishlist> {
+    self.wishlists.get(&account_id).unwrap_or_default()
+  }
+
+  // Products of the wishlist that are still listed
+  pub fn get_wishlist_products(
+    &self,
+    account_id: AccountId,
+    name: String,
+    from_index: Option<u64>,
+    limit: Option<u64>,
+  ) -> Vec<Product> {
+    let (from_index, limit) = pagination(from_index, limit);
+    let wishlists = self.wishlists.get(&account_id).unwrap_or_default();
+    let wishlist = wishlists.iter().find(|wishlist| wishlist.name == name).expect("Wishlist not found");
+    wishlist
+      .product_ids
+      .iter()
+      .filter_map(|product_id| self.product_by_id.get(product_id))
+      .filter(|product| !product.delisted)
+      .skip(from_index)
+      .take(limit)
+      .map(Product::with_current_price)
+      .collect()
+  }
+
+  // Accounts with the product in one of their wishlists, to notify them of price drops
+  pub fn get_wishlisters(&self, product_id: ProductId, from_index: Option<u64>, limit: Option<u64>) -> Vec<AccountId> {
+    let (from_index, limit) = pagination(from_index, limit);
+    match self.wishlisted_by.get(&product_id) {
+      Some(accounts) => accounts.as_vector().iter().skip(from_index).take(limit).collect(),
+      None => Vec::new(),
+    }
+  }
+
+  pub fn get_total_wishlisters(&self, product_id: ProductId) -> u64 {
+    self.wishlisted_by.get(&product_id).map(|accounts| accounts.len()).unwrap_or(0)
+  }
+}
+
+impl Contract {
+  // Drop the account from the wishlisters of the product once none of its wishlists has it
+  fn internal_unindex_wishlisted(&mut self, account_id: &AccountId, wishlists: &[Wishlist], product_id: &ProductId) {
+    if wishlists.iter().any(|wishlist| wishlist.product_ids.contains(product_id)) {
+      return;
+    }
+    if let Some(mut accounts) = self.wishlisted_by.get(product_id) {
+      accounts.remove(account_id);
+      if accounts.is_empty() {
+        self.wishlisted_by.remove(product_id);
+      } else {
+        self.wishlisted_by.insert(product_id, &accounts);
+      }
+    }
+  }
+
+  pub(crate) fn internal_notify_price_drop(&self, product: &Product, old_price: Balance) {
+    let wishlisters = self.get_total_wishlisters(product.product_id.clone());
+    if product.price < old_price && wishlisters > 0 {
+      Event::PriceDrop(vec![PriceDropData {
+        product_id: product.product_id.clone(),
+        old_price: U128(old_price),
+        new_price: U128(product.price),
+        wishlisters,
+      }])
+      .emit();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use near_sdk::test_utils::accounts;
+
+  use super::*;
+  use crate::test_utils::*;
+
+  fn used(contract: &Contract, account_id: AccountId) -> StorageUsage {
+    contract.storage_accounts.get(&account_id).unwrap().used
+  }
+
+  #[test]
+  fn wishlists_are_charged_to_their_account() {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(2));
+    let shop_used = used(&contract, accounts(1));
+
+    set_context(accounts(2), 0);
+    contract.add_to_wishlist("later".to_string(), "p1".to_string());
+    assert!(used(&contract, accounts(2)) > 0);
+    assert_eq!(used(&contract, accounts(1)), shop_used);
+
+    contract.delete_wishlist("later".to_string());
+    assert_eq!(used(&contract, accounts(2)), 0);
+    assert_eq!(contract.get_total_wishlisters("p1".to_string()), 0);
+  }
+
+  #[test]
+  fn removed_product_is_paid_back_on_removal_from_the_wishlist() {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_wishlist("later".to_string(), "p1".to_string());
+    set_context(accounts(1), 0);
+    contract.delete_product("p1".to_string());
+
+    set_context(accounts(2), 0);
+    assert!(contract.get_wishlist_products(accounts(2), "later".to_string(), None, None).is_empty());
+    contract.remove_from_wishlist("later".to_string(), "p1".to_string());
+    contract.delete_wishlist("later".to_string());
+    assert_eq!(used(&contract, accounts(2)), 0);
+    assert_eq!(contract.get_total_wishlisters("p1".to_string()), 0);
+  }
+
+  #[test]
+  #[should_panic(expected = "Register your account with storage_deposit first")]
+  fn wishlist_needs_a_storage_deposit() {
+    let mut contract = setup_contract();
+    set_context(accounts(2), 0);
+    contract.add_to_wishlist("later".to_string(), "p1".to_string());
+  }
+
+  #[test]
+  #[should_panic(expected = "Name is longer than 50 bytes")]
+  fn wishlist_name_is_capped() {
+    let mut contract = setup_contract();
+    register(&mut contract, accounts(2));
+    set_context(accounts(2), 0);
+    contract.add_to_wishlist("a".repeat(MAX_WISHLIST_NAME_LENGTH + 1), "p1".to_string());
+  }
+}